@@ -1,13 +1,35 @@
 use std::collections::HashSet;
+use std::ops::RangeInclusive;
 
 const INPUT: &str = include_str!("input/n10.input");
 
 fn main() {
     p1();
     p2();
+
+    let args = std::env::args().collect::<Vec<_>>();
+    let rules = parse_rules(&args);
+
+    if args.iter().any(|x| x == "--trailheads") {
+        for trailhead in trailheads(&input(), &rules) {
+            println!(
+                "{:?}: score {}, rating {}",
+                trailhead.pos, trailhead.score, trailhead.rating
+            );
+        }
+    }
+
+    if args.iter().any(|x| x == "--enumerate") {
+        enumerate(&rules);
+    }
+
+    if args.iter().any(|x| x == "--trails") {
+        print_trails(&rules);
+    }
 }
 
-type Map = Vec<Vec<u32>>;
+// Height of each tile, None for impassable tiles
+type Map = Vec<Vec<Option<u32>>>;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 struct Pos {
@@ -15,11 +37,66 @@ struct Pos {
     y: usize,
 }
 
-fn find_starts(map: &Map) -> Vec<Pos> {
+// What makes a trail: it goes from a tile of height `start` to one of height `end`,
+// each step changing the height by a value in `step` along one of the directions
+#[derive(Clone, Debug)]
+struct Rules {
+    start: u32,
+    end: u32,
+    step: RangeInclusive<i32>,
+    directions: Vec<(i32, i32)>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            start: 0,
+            end: 9,
+            step: 1..=1,
+            directions: vec![(0, 1), (0, -1), (1, 0), (-1, 0)],
+        }
+    }
+}
+
+impl Rules {
+    // Every step goes the same way, so trails can't loop and heights give a topological order
+    fn is_monotonic(&self) -> bool {
+        return *self.step.start() > 0 || *self.step.end() < 0;
+    }
+}
+
+fn parse_rules(args: &[String]) -> Rules {
+    let mut rules = Rules::default();
+    let value = |name: &str| {
+        args.iter()
+            .position(|x| x == name)
+            .map(|pos| args[pos + 1].as_str())
+    };
+
+    if let Some(start) = value("--start") {
+        rules.start = start.parse().unwrap();
+    }
+    if let Some(end) = value("--end") {
+        rules.end = end.parse().unwrap();
+    }
+    if let Some(step) = value("--step") {
+        let (a, b) = step.split_once("..").unwrap_or((step, step));
+        rules.step = a.parse().unwrap()..=b.parse().unwrap();
+    }
+    if args.iter().any(|x| x == "--diagonals") {
+        rules
+            .directions
+            .extend([(1, 1), (1, -1), (-1, 1), (-1, -1)]);
+    }
+
+    return rules;
+}
+
+fn find_starts(map: &Map, rules: &Rules) -> Vec<Pos> {
     let mut result = Vec::new();
     for y in 0..map.len() {
         for x in 0..map[y].len() {
-            if map[y][x] == 0 {
+            if map[y][x] == Some(rules.start) {
                 result.push(Pos { x: x, y: y });
             }
         }
@@ -27,88 +104,320 @@ fn find_starts(map: &Map) -> Vec<Pos> {
     return result;
 }
 
-fn walk(map: &Map, pos: &Pos, next: u32, trail_ends: &mut HashSet<Pos>) {
-    if map[pos.y][pos.x] == 9 && next == 10 {
-        trail_ends.insert(*pos);
-        return;
-    }
+// Tiles a trail can continue to from the position
+fn next_steps(map: &Map, pos: &Pos, rules: &Rules) -> Vec<Pos> {
+    let mut result = Vec::with_capacity(rules.directions.len());
+    let height = match map[pos.y][pos.x] {
+        Some(height) => height as i32,
+        None => return result,
+    };
 
-    let scan_pos = [(0, 1), (0, -1), (1, 0), (-1, 0)];
-    for (dx, dy) in scan_pos {
-        if (pos.x as i32 + dx) < 0
-            || (pos.y as i32 + dy) < 0
-            || (pos.y as i32 + dy) as usize >= map.len()
-            || (pos.x as i32 + dx) as usize >= map[(pos.y as i32 + dy) as usize].len()
-        {
+    for (dx, dy) in rules.directions.iter() {
+        let (x, y) = (pos.x as i32 + dx, pos.y as i32 + dy);
+        if x < 0 || y < 0 || y as usize >= map.len() || x as usize >= map[y as usize].len() {
             continue;
         }
-        let (x, y) = ((pos.x as i32 + dx) as usize, (pos.y as i32 + dy) as usize);
-        if map[y][x] == next {
-            walk(map, &Pos { x: x, y: y }, next + 1, trail_ends);
+
+        let (x, y) = (x as usize, y as usize);
+        if let Some(next) = map[y][x] {
+            if rules.step.contains(&(next as i32 - height)) {
+                result.push(Pos { x: x, y: y });
+            }
         }
     }
+
+    return result;
 }
 
-fn walk_path(map: &Map, pos: &Pos, next: u32, trail_ends: &mut HashSet<Vec<Pos>>, path: &Vec<Pos>) {
-    if map[pos.y][pos.x] == 9 && next == 10 {
-        trail_ends.insert(path.to_vec());
+// Every trail from the position, a trail never goes through the same tile twice
+fn trails(map: &Map, start: &Pos, rules: &Rules) -> Vec<Vec<Pos>> {
+    let mut result = Vec::new();
+    let mut path = vec![*start];
+    walk_path(map, rules, &mut path, &mut result);
+    return result;
+}
+
+fn walk_path(map: &Map, rules: &Rules, path: &mut Vec<Pos>, trails: &mut Vec<Vec<Pos>>) {
+    let pos = *path.last().unwrap();
+    if path.len() > 1 && map[pos.y][pos.x] == Some(rules.end) {
+        trails.push(path.clone());
         return;
     }
 
-    let scan_pos = [(0, 1), (0, -1), (1, 0), (-1, 0)];
-    for (dx, dy) in scan_pos {
-        if (pos.x as i32 + dx) < 0
-            || (pos.y as i32 + dy) < 0
-            || (pos.y as i32 + dy) as usize >= map.len()
-            || (pos.x as i32 + dx) as usize >= map[(pos.y as i32 + dy) as usize].len()
-        {
+    for next in next_steps(map, &pos, rules) {
+        if !rules.is_monotonic() && path.contains(&next) {
             continue;
         }
-        let (x, y) = ((pos.x as i32 + dx) as usize, (pos.y as i32 + dy) as usize);
-        if map[y][x] == next {
-            let pos = Pos { x: x, y: y };
-            let mut path = path.clone();
 
-            path.push(pos);
-            walk_path(map, &pos, next + 1, trail_ends, &path);
-        }
+        path.push(next);
+        walk_path(map, rules, path, trails);
+        path.pop();
     }
 }
 
 fn input() -> Map {
+    return parse(INPUT);
+}
+
+fn parse(text: &str) -> Map {
     let mut map = Vec::new();
-    for line in INPUT.trim().lines() {
+    for line in text.trim().lines() {
         map.push(Vec::new());
         let pos = map.len() - 1;
         for c in line.chars() {
-            map[pos].push(c.to_digit(10).unwrap());
+            map[pos].push(c.to_digit(10));
         }
     }
 
     return map;
 }
 
+// Score (summits reached) and rating (distinct trails) of a trailhead
+struct Trailhead {
+    pos: Pos,
+    score: usize,
+    rating: usize,
+}
+
+fn trailheads(map: &Map, rules: &Rules) -> Vec<Trailhead> {
+    if !rules.is_monotonic() {
+        return trailheads_enumerated(map, rules);
+    }
+
+    // Visit the tiles so the ones a trail continues to are always done first
+    let mut order = Vec::new();
+    for y in 0..map.len() {
+        for x in 0..map[y].len() {
+            if let Some(height) = map[y][x] {
+                order.push((height, Pos { x: x, y: y }));
+            }
+        }
+    }
+    order.sort_by_key(|(height, _)| *height);
+    if *rules.step.start() > 0 {
+        order.reverse();
+    }
+
+    let summit_count = order.iter().filter(|(x, _)| *x == rules.end).count();
+    let words = summit_count.div_ceil(64);
+    let mut ratings = vec![vec![0; map[0].len()]; map.len()];
+    let mut summits = vec![vec![Vec::<u64>::new(); map[0].len()]; map.len()];
+    let mut summit_id = 0;
+
+    // The rating is the sum of the ratings of the next tiles, and the reachable summits
+    // are the union of their summit bitsets
+    for (height, pos) in order.iter() {
+        if *height == rules.end {
+            ratings[pos.y][pos.x] = 1;
+            summits[pos.y][pos.x] = vec![0; words];
+            summits[pos.y][pos.x][summit_id / 64] |= 1 << (summit_id % 64);
+            summit_id += 1;
+            continue;
+        }
+
+        let (rating, reached) = combine(map, pos, rules, &ratings, &summits, words);
+        if rating > 0 {
+            ratings[pos.y][pos.x] = rating;
+            summits[pos.y][pos.x] = reached;
+        }
+    }
+
+    // A trail takes at least one step, so a trailhead that is also a summit
+    // only counts the trails through the tiles after it
+    return find_starts(map, rules)
+        .into_iter()
+        .map(|pos| {
+            let (rating, reached) = combine(map, &pos, rules, &ratings, &summits, words);
+            Trailhead {
+                pos: pos,
+                score: reached.iter().map(|x| x.count_ones() as usize).sum(),
+                rating: rating,
+            }
+        })
+        .collect();
+}
+
+// Trails and reachable summits through the tiles a trail can continue to from the position
+fn combine(
+    map: &Map,
+    pos: &Pos,
+    rules: &Rules,
+    ratings: &[Vec<usize>],
+    summits: &[Vec<Vec<u64>>],
+    words: usize,
+) -> (usize, Vec<u64>) {
+    let mut rating = 0;
+    let mut reached = vec![0; words];
+    for next in next_steps(map, pos, rules) {
+        if ratings[next.y][next.x] == 0 {
+            continue;
+        }
+
+        rating += ratings[next.y][next.x];
+        for (word, other) in reached.iter_mut().zip(summits[next.y][next.x].iter()) {
+            *word |= other;
+        }
+    }
+
+    return (rating, reached);
+}
+
+// Same as `trailheads`, listing every trail
+fn trailheads_enumerated(map: &Map, rules: &Rules) -> Vec<Trailhead> {
+    return find_starts(map, rules)
+        .into_iter()
+        .map(|pos| {
+            let trails = trails(map, &pos, rules);
+            let summits = trails
+                .iter()
+                .map(|x| *x.last().unwrap())
+                .collect::<HashSet<_>>();
+            Trailhead {
+                pos: pos,
+                score: summits.len(),
+                rating: trails.len(),
+            }
+        })
+        .collect();
+}
+
+// Map with only the tiles of the trail
+fn render_trail(map: &Map, trail: &[Pos]) -> String {
+    let mut result = String::new();
+    for y in 0..map.len() {
+        for x in 0..map[y].len() {
+            let on_trail = trail.contains(&Pos { x: x, y: y });
+            result.push(match map[y][x] {
+                Some(height) if on_trail => char::from_digit(height, 10).unwrap(),
+                _ => '.',
+            });
+        }
+        result.push('\n');
+    }
+
+    return result;
+}
+
 fn p1() {
     let map = input();
-    let starts = find_starts(&map);
-    let mut scores = 0;
-    for start in starts {
-        let mut result = HashSet::new();
-        walk(&map, &start, 1, &mut result);
-        scores += result.len();
-    }
+    let rules = Rules::default();
+    let scores = trailheads(&map, &rules)
+        .iter()
+        .map(|x| x.score)
+        .sum::<usize>();
 
     println!("Result 1: {}", scores)
 }
+
 fn p2() {
     let map = input();
-    let starts = find_starts(&map);
-    let mut scores = 0;
-    for start in starts {
-        let mut result = HashSet::new();
-        walk_path(&map, &start, 1, &mut result, &vec![start]);
-        scores += result.len();
+    let rules = Rules::default();
+    let ratings = trailheads(&map, &rules)
+        .iter()
+        .map(|x| x.rating)
+        .sum::<usize>();
+
+    println!("Result 2: {}", ratings)
+}
+
+// Same as p1 and p2, enumerating every trail instead
+fn enumerate(rules: &Rules) {
+    let map = input();
+    let trailheads = trailheads_enumerated(&map, rules);
+
+    println!(
+        "Result 1: {}",
+        trailheads.iter().map(|x| x.score).sum::<usize>()
+    );
+    println!(
+        "Result 2: {}",
+        trailheads.iter().map(|x| x.rating).sum::<usize>()
+    );
+}
+
+fn print_trails(rules: &Rules) {
+    let map = input();
+    for start in find_starts(&map, rules) {
+        let trails = trails(&map, &start, rules);
+        println!("{:?}: {} trails", start, trails.len());
+        for trail in trails {
+            println!("{}", render_trail(&map, &trail));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EXAMPLE: &str = "
+89010123
+78121874
+87430965
+96549874
+45678903
+32019012
+01329801
+10456732
+";
+
+    fn results(trailheads: Vec<Trailhead>) -> Vec<(Pos, usize, usize)> {
+        return trailheads
+            .into_iter()
+            .map(|x| (x.pos, x.score, x.rating))
+            .collect();
     }
 
-    println!("Result 2: {}", scores)
+    #[test]
+    fn test_trailheads() {
+        let map = parse(EXAMPLE);
+        let mut diagonals = Rules::default();
+        diagonals
+            .directions
+            .extend([(1, 1), (1, -1), (-1, 1), (-1, -1)]);
+        let rules = [
+            Rules::default(),
+            diagonals,
+            Rules {
+                step: 1..=2,
+                ..Rules::default()
+            },
+            Rules {
+                start: 9,
+                end: 0,
+                step: -1..=-1,
+                ..Rules::default()
+            },
+            Rules {
+                start: 5,
+                end: 5,
+                ..Rules::default()
+            },
+            Rules {
+                start: 5,
+                end: 5,
+                step: -1..=1,
+                ..Rules::default()
+            },
+        ];
+
+        let default = results(trailheads(&map, &rules[0]));
+        assert_eq!(36, default.iter().map(|x| x.1).sum::<usize>());
+        assert_eq!(81, default.iter().map(|x| x.2).sum::<usize>());
+
+        // A trail takes at least one step, and can't start and end at the same height
+        // when every step goes up
+        let same = results(trailheads(&map, &rules[4]));
+        assert_eq!(4, same.len());
+        assert!(same.iter().all(|x| x.1 == 0 && x.2 == 0));
+
+        for rules in rules.iter() {
+            assert_eq!(
+                results(trailheads_enumerated(&map, rules)),
+                results(trailheads(&map, rules)),
+                "{:?}",
+                rules
+            );
+        }
+    }
 }
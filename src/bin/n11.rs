@@ -1,12 +1,136 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
 
 const INPUT: &str = include_str!("input/n11.input");
 
 type Line = Vec<u64>;
 
+// Rules are tried in order, the first one matching the stone is applied
+const DEFAULT_RULES: &str = "
+= 0 -> set 1
+even-digits -> split
+any -> mul 2024
+";
+
 fn main() {
     p1();
     p2();
+
+    let args = std::env::args().collect::<Vec<_>>();
+    let rules = match args.iter().position(|x| x == "--rules") {
+        Some(pos) => parse_rules(&std::fs::read_to_string(&args[pos + 1]).unwrap()),
+        None => parse_rules(DEFAULT_RULES),
+    };
+    let value = |name: &str| {
+        args.iter()
+            .position(|x| x == name)
+            .map(|pos| args[pos + 1].parse::<u64>().unwrap())
+    };
+
+    if args.iter().any(|x| x == "--closure") {
+        analyze(&rules, value("--blinks"), value("--modulo"));
+    } else if let Some(blinks) = value("--blinks") {
+        p3(
+            &rules,
+            blinks as u32,
+            args.iter().any(|x| x == "--generations"),
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Predicate {
+    Any,
+    Equals(u64),
+    EvenDigits,
+    OddDigits,
+    DivisibleBy(u64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Transform {
+    Set(u64),
+    // Left and right halves of the digits
+    Split,
+    Mul(u64),
+    Add(u64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Rule {
+    predicate: Predicate,
+    transform: Transform,
+}
+
+// One rule per line as `<predicate> -> <transform>`, `#` starts a comment.
+// Predicates: `any`, `= N`, `even-digits`, `odd-digits`, `divisible N`.
+// Transforms: `set N`, `split`, `mul N`, `add N`.
+fn parse_rules(text: &str) -> Vec<Rule> {
+    let mut result = Vec::new();
+    for line in text.lines() {
+        let line = line.split("#").next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (predicate, transform) = line.split_once("->").expect("missing ->");
+        let predicate = predicate.split_whitespace().collect::<Vec<_>>();
+        let transform = transform.split_whitespace().collect::<Vec<_>>();
+        let arg = |x: &[&str]| -> u64 { x[1].parse().expect("invalid number") };
+
+        result.push(Rule {
+            predicate: match predicate[0] {
+                "any" => Predicate::Any,
+                "=" => Predicate::Equals(arg(&predicate)),
+                "even-digits" => Predicate::EvenDigits,
+                "odd-digits" => Predicate::OddDigits,
+                "divisible" => Predicate::DivisibleBy(arg(&predicate)),
+                x => panic!("unknown predicate {}", x),
+            },
+            transform: match transform[0] {
+                "set" => Transform::Set(arg(&transform)),
+                "split" => Transform::Split,
+                "mul" => Transform::Mul(arg(&transform)),
+                "add" => Transform::Add(arg(&transform)),
+                x => panic!("unknown transform {}", x),
+            },
+        });
+    }
+
+    return result;
+}
+
+impl Predicate {
+    fn matches(&self, rock: u64) -> bool {
+        let even_digits = rock >= 10 && rock.ilog10() % 2 == 1;
+        return match self {
+            Predicate::Any => true,
+            Predicate::Equals(x) => rock == *x,
+            Predicate::EvenDigits => even_digits,
+            Predicate::OddDigits => !even_digits,
+            Predicate::DivisibleBy(x) => rock.is_multiple_of(*x),
+        };
+    }
+}
+
+// Stones replacing the rock, it stays the same if no rule matches.
+// None if the new value does not fit in a u64.
+fn apply(rules: &[Rule], rock: u64) -> Option<Vec<u64>> {
+    let rule = match rules.iter().find(|x| x.predicate.matches(rock)) {
+        Some(rule) => rule,
+        None => return Some(vec![rock]),
+    };
+
+    return match rule.transform {
+        Transform::Set(x) => Some(vec![x]),
+        Transform::Split if rock < 10 => Some(vec![rock]),
+        Transform::Split => {
+            let (a, b) = split_digits(rock);
+            Some(vec![a, b])
+        }
+        Transform::Mul(x) => Some(vec![rock.checked_mul(x)?]),
+        Transform::Add(x) => Some(vec![rock.checked_add(x)?]),
+    };
 }
 
 fn input() -> Line {
@@ -18,68 +142,420 @@ fn input() -> Line {
 }
 
 fn p1() {
+    let rules = parse_rules(DEFAULT_RULES);
     let mut result = input();
     for _ in 0..25 {
-        result = blink(&result);
+        result = blink(&result, &rules);
     }
 
     println!("Result1: {}", result.len())
 }
 
 fn p2() {
-    let input = input();
-    let mut memo = HashMap::new();
-    let mut result = 0;
-    for x in input.iter() {
-        result += blink_and_count(*x, 75, &mut memo);
+    let rules = parse_rules(DEFAULT_RULES);
+    let generations = simulate(&input(), &rules, 75).unwrap();
+
+    println!("Result2: {}", generations.last().unwrap().stones)
+}
+
+fn p3(rules: &[Rule], blinks: u32, show_generations: bool) {
+    let generations = match simulate(&input(), rules, blinks) {
+        Ok(generations) => generations,
+        Err(overflow) => {
+            println!(
+                "Stone {} overflows at blink {}",
+                overflow.rock, overflow.blink
+            );
+            return;
+        }
+    };
+
+    if show_generations {
+        for (blink, generation) in generations.iter().enumerate() {
+            println!(
+                "Blink {}: {} stones, {} distinct",
+                blink, generation.stones, generation.distinct
+            );
+        }
     }
 
-    println!("Result2: {}", result)
+    println!(
+        "Result after {} blinks: {}",
+        blinks,
+        generations.last().unwrap().stones
+    )
 }
 
-fn blink(line: &Line) -> Line {
+fn blink(line: &Line, rules: &[Rule]) -> Line {
     let mut result = Vec::with_capacity(line.len());
     for rock in line.iter() {
-        if *rock == 0 {
-            result.push(1);
-        } else if *rock >= 10 && rock.ilog10() % 2 == 1 {
-            let (a, b) = split_digits(*rock);
-            result.push(a);
-            result.push(b);
-        } else {
-            result.push(rock * 2024);
+        result.extend(apply(rules, *rock).expect("stone value overflow"));
+    }
+
+    return result;
+}
+
+// Number of stones, with as many 64 bit limbs as needed (least significant first)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Count {
+    limbs: Vec<u64>,
+}
+
+impl Count {
+    fn add(&mut self, other: &Count) {
+        let mut carry = false;
+        for i in 0..self.limbs.len().max(other.limbs.len()) {
+            if i == self.limbs.len() {
+                self.limbs.push(0);
+            }
+            let (sum, c1) = self.limbs[i].overflowing_add(*other.limbs.get(i).unwrap_or(&0));
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            self.limbs[i] = sum;
+            carry = c1 || c2;
+        }
+
+        if carry {
+            self.limbs.push(1);
+        }
+    }
+}
+
+impl From<u64> for Count {
+    fn from(x: u64) -> Self {
+        return Count { limbs: vec![x] };
+    }
+}
+
+impl fmt::Display for Count {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Split in chunks of 19 decimal digits, dividing from the most significant limb
+        const CHUNK: u64 = 10_000_000_000_000_000_000;
+        let mut limbs = self.limbs.clone();
+        let mut chunks = Vec::new();
+        while limbs.iter().any(|x| *x != 0) {
+            let mut rem = 0u128;
+            for limb in limbs.iter_mut().rev() {
+                let current = (rem << 64) | *limb as u128;
+                *limb = (current / CHUNK as u128) as u64;
+                rem = current % CHUNK as u128;
+            }
+            chunks.push(rem as u64);
+        }
+
+        match chunks.pop() {
+            None => write!(f, "0")?,
+            Some(first) => write!(f, "{}", first)?,
+        }
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:019}", chunk)?;
+        }
+        return Ok(());
+    }
+}
+
+struct Generation {
+    stones: Count,
+    distinct: usize,
+}
+
+#[derive(Debug)]
+struct Overflow {
+    rock: u64,
+    blink: u32,
+}
+
+// Stones only depend on their value, so each generation is kept as the number of
+// stones with each value. Returns the initial state and every blink after it.
+fn simulate(line: &Line, rules: &[Rule], blinks: u32) -> Result<Vec<Generation>, Overflow> {
+    let mut current = HashMap::<u64, Count>::new();
+    for rock in line {
+        current.entry(*rock).or_default().add(&Count::from(1));
+    }
+
+    let mut result = Vec::with_capacity(blinks as usize + 1);
+    result.push(generation(&current));
+    for blink in 1..blinks + 1 {
+        let mut next = HashMap::<u64, Count>::new();
+        for (rock, count) in current.iter() {
+            let stones = apply(rules, *rock).ok_or(Overflow {
+                rock: *rock,
+                blink: blink,
+            })?;
+            for stone in stones {
+                next.entry(stone).or_default().add(count);
+            }
+        }
+
+        current = next;
+        result.push(generation(&current));
+    }
+
+    return Ok(result);
+}
+
+fn generation(stones: &HashMap<u64, Count>) -> Generation {
+    let mut total = Count::default();
+    for count in stones.values() {
+        total.add(count);
+    }
+
+    return Generation {
+        stones: total,
+        distinct: stones.len(),
+    };
+}
+
+// Every stone value reachable from the line, in the order they are found,
+// and the values each one turns into on a blink
+struct Closure {
+    values: Vec<u64>,
+    index: HashMap<u64, usize>,
+    next: Vec<Vec<usize>>,
+}
+
+// Some rules never stop making new values, so the closure is given up at some point
+const MAX_CLOSURE: usize = 1_000_000;
+// The matrix has a row and a column per value that keeps coming back
+const MAX_MATRIX: usize = 500;
+// Blinks done one at a time when there are too many of those values for the matrix
+const MAX_STEPS: u64 = 10_000;
+
+fn closure(line: &Line, rules: &[Rule]) -> Result<Closure, String> {
+    let mut result = Closure {
+        values: Vec::new(),
+        index: HashMap::new(),
+        next: Vec::new(),
+    };
+    let mut pending = line.iter().copied().collect::<VecDeque<_>>();
+    while let Some(rock) = pending.pop_front() {
+        if result.index.contains_key(&rock) {
+            continue;
+        }
+        if result.values.len() == MAX_CLOSURE {
+            return Err(format!(
+                "more than {} distinct stones, the limit for the analysis",
+                MAX_CLOSURE
+            ));
+        }
+
+        result.index.insert(rock, result.values.len());
+        result.values.push(rock);
+        let stones = apply(rules, rock).ok_or(format!("stone {} overflows", rock))?;
+        pending.extend(stones);
+    }
+
+    // Every value is indexed now
+    for rock in result.values.iter() {
+        let stones = apply(rules, *rock).unwrap();
+        result
+            .next
+            .push(stones.iter().map(|x| result.index[x]).collect());
+    }
+
+    return Ok(result);
+}
+
+// Values that can still be reached from the ones with stones, sorted
+fn reachable(closure: &Closure, counts: &[u128]) -> Vec<usize> {
+    let mut seen = vec![false; counts.len()];
+    let mut pending = (0..counts.len())
+        .filter(|x| counts[*x] != 0)
+        .collect::<Vec<_>>();
+    for value in pending.iter() {
+        seen[*value] = true;
+    }
+    while let Some(value) = pending.pop() {
+        for next in closure.next[value].iter() {
+            if !seen[*next] {
+                seen[*next] = true;
+                pending.push(*next);
+            }
+        }
+    }
+
+    return (0..counts.len()).filter(|x| seen[*x]).collect();
+}
+
+// Stones of each value after a blink, either modulo the value or None if it overflows
+fn step(closure: &Closure, counts: &[u128], modulo: Option<u64>) -> Option<Vec<u128>> {
+    let mut result = vec![0u128; counts.len()];
+    for (value, count) in counts.iter().enumerate() {
+        if *count == 0 {
+            continue;
+        }
+        for next in closure.next[value].iter() {
+            result[*next] = match modulo {
+                Some(m) => (result[*next] + count) % m as u128,
+                None => result[*next].checked_add(*count)?,
+            };
+        }
+    }
+
+    return Some(result);
+}
+
+type Matrix = Vec<Vec<u128>>;
+
+// Entry (to, from) is how many stones of value `to` a stone of value `from` turns into,
+// over the given values of the closure
+fn transition_matrix(closure: &Closure, values: &[usize]) -> Matrix {
+    let local = values
+        .iter()
+        .enumerate()
+        .map(|(i, x)| (*x, i))
+        .collect::<HashMap<_, _>>();
+    let mut result = vec![vec![0; values.len()]; values.len()];
+    for (from, value) in values.iter().enumerate() {
+        for next in closure.next[*value].iter() {
+            result[local[next]][from] += 1;
         }
     }
 
     return result;
 }
 
-fn blink_and_count(rock: u64, depth: u32, memo: &mut HashMap<(u64, u32), u64>) -> u64 {
-    if let Some(r) = memo.get(&(rock, depth)) {
-        // println!("Match {}", r);
-        return *r;
+// Product of the matrices, either modulo the value or None if it overflows
+fn multiply(a: &Matrix, b: &Matrix, modulo: Option<u64>) -> Option<Matrix> {
+    let size = a.len();
+    let mut result = vec![vec![0u128; size]; size];
+    for i in 0..size {
+        for k in 0..size {
+            if a[i][k] == 0 {
+                continue;
+            }
+            for j in 0..size {
+                if b[k][j] == 0 {
+                    continue;
+                }
+                result[i][j] = match modulo {
+                    Some(m) => (result[i][j] + a[i][k] * b[k][j]) % m as u128,
+                    None => result[i][j].checked_add(a[i][k].checked_mul(b[k][j])?)?,
+                };
+            }
+        }
+    }
+
+    return Some(result);
+}
+
+fn power(matrix: &Matrix, mut exponent: u64, modulo: Option<u64>) -> Option<Matrix> {
+    let size = matrix.len();
+    let mut result = (0..size)
+        .map(|i| (0..size).map(|j| (i == j) as u128).collect())
+        .collect::<Matrix>();
+    let mut base = matrix.clone();
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = multiply(&result, &base, modulo)?;
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            base = multiply(&base, &base, modulo)?;
+        }
+    }
+
+    return Some(result);
+}
+
+// Stones after the blinks. Values that are not on a cycle are only seen in the first blinks,
+// so those are done one at a time, until every value that can still be reached is reached
+// again after a blink. The rest of the blinks power the matrix over only those values,
+// or are also done one at a time if there are too many of them.
+fn count_with_matrix(
+    line: &Line,
+    closure: &Closure,
+    blinks: u64,
+    modulo: Option<u64>,
+) -> Result<u128, String> {
+    let overflow = || "the count does not fit in a u128".to_string();
+    let mut counts = vec![0u128; closure.values.len()];
+    for rock in line {
+        let value = closure.index[rock];
+        counts[value] = match modulo {
+            Some(m) => (counts[value] + 1) % m as u128,
+            None => counts[value] + 1,
+        };
     }
 
-    if depth == 0 {
-        return 1;
+    let mut blink = 0;
+    let mut values = reachable(closure, &counts);
+    while blink < blinks {
+        counts = step(closure, &counts, modulo).ok_or_else(overflow)?;
+        blink += 1;
+
+        let next = reachable(closure, &counts);
+        if next.len() == values.len() {
+            break;
+        }
+        values = next;
     }
 
-    if rock == 0 {
-        let r = blink_and_count(1, depth - 1, memo);
-        memo.insert((rock, depth), r);
-        return r;
+    let remaining = blinks - blink;
+    if remaining > 0 && values.len() <= MAX_MATRIX {
+        let matrix = transition_matrix(closure, &values);
+        let powered = power(&matrix, remaining, modulo).ok_or_else(overflow)?;
+        let mut result = vec![0u128; closure.values.len()];
+        for (i, to) in values.iter().enumerate() {
+            for (j, from) in values.iter().enumerate() {
+                let stones = match modulo {
+                    Some(m) => powered[i][j] * counts[*from] % m as u128,
+                    None => powered[i][j]
+                        .checked_mul(counts[*from])
+                        .ok_or_else(overflow)?,
+                };
+                result[*to] = match modulo {
+                    Some(m) => (result[*to] + stones) % m as u128,
+                    None => result[*to].checked_add(stones).ok_or_else(overflow)?,
+                };
+            }
+        }
+        counts = result;
+    } else if remaining <= MAX_STEPS {
+        for _ in 0..remaining {
+            counts = step(closure, &counts, modulo).ok_or_else(overflow)?;
+        }
+    } else {
+        return Err(format!(
+            "{} values keep coming back, more than the limit of {} for the matrix, \
+            and {} blinks are more than the limit of {} to do one at a time",
+            values.len(),
+            MAX_MATRIX,
+            remaining,
+            MAX_STEPS
+        ));
     }
-    if rock >= 10 && rock.ilog10() % 2 == 1 {
-        let (a, b) = split_digits(rock);
-        let r = blink_and_count(a, depth - 1, memo) + blink_and_count(b, depth - 1, memo);
 
-        memo.insert((rock, depth), r);
-        return r;
+    let mut result = 0u128;
+    for count in counts {
+        result = match modulo {
+            Some(m) => (result + count) % m as u128,
+            None => result.checked_add(count).ok_or_else(overflow)?,
+        };
     }
 
-    let r = blink_and_count(rock * 2024, depth - 1, memo);
-    memo.insert((rock, depth), r);
-    return r;
+    return Ok(result);
+}
+
+fn analyze(rules: &[Rule], blinks: Option<u64>, modulo: Option<u64>) {
+    let line = input();
+    let closure = match closure(&line, rules) {
+        Ok(closure) => closure,
+        Err(e) => {
+            println!("Closure not computed: {}", e);
+            return;
+        }
+    };
+    println!("Closure: {} values", closure.values.len());
+
+    if let Some(blinks) = blinks {
+        match count_with_matrix(&line, &closure, blinks, modulo) {
+            Ok(count) => match modulo {
+                Some(m) => println!("Stones after {} blinks: {} (mod {})", blinks, count, m),
+                None => println!("Stones after {} blinks: {}", blinks, count),
+            },
+            Err(e) => println!("Stones after {} blinks not computed: {}", blinks, e),
+        }
+    }
 }
 
 fn split_digits(x: u64) -> (u64, u64) {
@@ -87,3 +563,59 @@ fn split_digits(x: u64) -> (u64, u64) {
     let multiplier = 10u64.pow(digit_count / 2);
     return (x / multiplier, x % multiplier);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_count() {
+        let mut count = Count::from(u64::MAX);
+        count.add(&Count::from(1));
+        assert_eq!("18446744073709551616", count.to_string());
+        count.add(&count.clone());
+        assert_eq!("36893488147419103232", count.to_string());
+        assert_eq!("0", Count::default().to_string());
+        assert_eq!(
+            "10000000000000000000",
+            Count::from(10_000_000_000_000_000_000).to_string()
+        );
+    }
+
+    #[test]
+    fn test_matrix() {
+        let rules = parse_rules(DEFAULT_RULES);
+        let lines = [
+            vec![125, 17],
+            vec![0, 7, 6618216, 26481, 885, 42, 202642, 8791],
+        ];
+        for line in lines {
+            let closure = closure(&line, &rules).unwrap();
+            for blinks in [0, 1, 6, 25, 75, 150] {
+                let expected = simulate(&line, &rules, blinks).unwrap();
+                assert_eq!(
+                    expected.last().unwrap().stones.to_string(),
+                    count_with_matrix(&line, &closure, blinks as u64, None)
+                        .unwrap()
+                        .to_string()
+                );
+            }
+        }
+
+        // Few enough values keep coming back from the example to use the matrix
+        let line = vec![125, 17];
+        let closure = closure(&line, &rules).unwrap();
+        let modulo = Some(1_000_000_007);
+        let mut counts = vec![0u128; closure.values.len()];
+        for rock in line.iter() {
+            counts[closure.index[rock]] += 1;
+        }
+        for _ in 0..1000 {
+            counts = step(&closure, &counts, modulo).unwrap();
+        }
+        assert_eq!(
+            counts.iter().sum::<u128>() % 1_000_000_007,
+            count_with_matrix(&line, &closure, 1000, modulo).unwrap()
+        );
+    }
+}
@@ -1,10 +1,9 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 const INPUT: &str = include_str!("input/n12.input");
 
 type Node = char;
 type Map = Vec<Vec<Node>>;
-type Region = HashSet<Pos>;
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 struct Pos {
@@ -19,27 +18,31 @@ fn input() -> Map {
 fn main() {
     p1();
     p2();
+
+    let args = std::env::args().collect::<Vec<_>>();
+    let connectivity = match args.iter().any(|x| x == "--diagonals") {
+        true => Connectivity::Eight,
+        false => Connectivity::Four,
+    };
+
+    if let Some(pos) = args.iter().position(|x| x == "--price") {
+        match Formula::parse(&args[pos + 1]) {
+            Ok(formula) => print_price(&formula, connectivity),
+            Err(error) => println!("Invalid formula: {}", error),
+        }
+    }
+
+    if args.iter().any(|x| x == "--report") {
+        print_report(connectivity, args.iter().any(|x| x == "--outline"));
+    }
 }
 fn p1() {
     let map = input();
-    let mut regions = Vec::<Region>::new();
-    for y in 0..map.len() {
-        for x in 0..map[y].len() {
-            let pos = Pos { x: x, y: y };
-            if !regions
-                .iter()
-                .fold(false, |acc, set| acc || set.contains(&pos))
-            {
-                let mut region = Region::new();
-                build_region(&map, map[pos.y][pos.x], &pos, &mut region);
-                regions.push(region);
-            }
-        }
-    }
+    let labels = label(&map, Connectivity::Four);
 
     let mut cost = 0;
-    for region in regions {
-        cost += area(&region) * perimeter(&region, &map);
+    for region in labels.regions.iter() {
+        cost += region.area() * region.perimeter;
     }
 
     println!("Result1: {}", cost);
@@ -47,119 +50,509 @@ fn p1() {
 
 fn p2() {
     let map = input();
-    let mut regions = Vec::<Region>::new();
+    let labels = label(&map, Connectivity::Four);
+
+    let mut cost = 0;
+    for (id, region) in labels.regions.iter().enumerate() {
+        cost += region.area() * sides(&region.cells, id, &labels);
+    }
+
+    println!("Result2: {}", cost);
+}
+
+// Whether cells touching by a corner are part of the same region
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Connectivity {
+    Four,
+    Eight,
+}
+
+impl Connectivity {
+    fn deltas(self) -> &'static [(i32, i32)] {
+        return match self {
+            Connectivity::Four => &[(-1, 0), (1, 0), (0, -1), (0, 1)],
+            Connectivity::Eight => &[
+                (-1, 0),
+                (1, 0),
+                (0, -1),
+                (0, 1),
+                (1, 1),
+                (1, -1),
+                (-1, 1),
+                (-1, -1),
+            ],
+        };
+    }
+
+    // Connectivity of the cells outside of a region, so the outside can't cross
+    // the region where it only touches by a corner and the other way around
+    fn complement(self) -> Connectivity {
+        return match self {
+            Connectivity::Four => Connectivity::Eight,
+            Connectivity::Eight => Connectivity::Four,
+        };
+    }
+}
+
+struct Summary {
+    node: Node,
+    cells: Vec<Pos>,
+    perimeter: usize,
+}
+
+impl Summary {
+    fn area(&self) -> usize {
+        return self.cells.len();
+    }
+}
+
+// Region id of every cell, and the regions indexed by id
+struct Labels {
+    grid: Vec<Vec<usize>>,
+    regions: Vec<Summary>,
+    connectivity: Connectivity,
+}
+
+impl Labels {
+    fn get(&self, pos: &Pos) -> usize {
+        return self.grid[pos.y][pos.x];
+    }
+
+    // Region of the cell at the offset from the position, None outside of the map
+    fn neighbour(&self, pos: &Pos, (dx, dy): (i32, i32)) -> Option<usize> {
+        let (x, y) = (pos.x as isize + dx as isize, pos.y as isize + dy as isize);
+        if x < 0 || y < 0 || y as usize >= self.grid.len() || x as usize >= self.grid[0].len() {
+            return None;
+        }
+        return Some(self.grid[y as usize][x as usize]);
+    }
+}
+
+// Flood fill each unlabelled cell with an explicit stack, so every cell is visited once
+fn label(map: &Map, connectivity: Connectivity) -> Labels {
+    let mut labels = Labels {
+        grid: map.iter().map(|x| vec![usize::MAX; x.len()]).collect(),
+        regions: Vec::new(),
+        connectivity: connectivity,
+    };
+
     for y in 0..map.len() {
         for x in 0..map[y].len() {
-            let pos = Pos { x: x, y: y };
-            if !regions
-                .iter()
-                .fold(false, |acc, set| acc || set.contains(&pos))
-            {
-                let mut region = Region::new();
-                build_region(&map, map[pos.y][pos.x], &pos, &mut region);
-                regions.push(region);
+            if labels.grid[y][x] != usize::MAX {
+                continue;
             }
+
+            let id = labels.regions.len();
+            let node = map[y][x];
+            let mut region = Summary {
+                node: node,
+                cells: Vec::new(),
+                perimeter: 0,
+            };
+
+            labels.grid[y][x] = id;
+            let mut pending = vec![Pos { x: x, y: y }];
+            while let Some(pos) = pending.pop() {
+                region.perimeter += 4;
+                for delta in connectivity.deltas() {
+                    if let Some(next) = next_pos(&pos, map, *delta) {
+                        if map[next.y][next.x] != node {
+                            continue;
+                        }
+
+                        // Only cells sharing an edge have no fence between them
+                        if delta.0 == 0 || delta.1 == 0 {
+                            region.perimeter -= 1;
+                        }
+                        if labels.grid[next.y][next.x] == usize::MAX {
+                            labels.grid[next.y][next.x] = id;
+                            pending.push(next);
+                        }
+                    }
+                }
+                region.cells.push(pos);
+            }
+
+            labels.regions.push(region);
         }
     }
 
-    let mut cost = 0;
-    for region in regions {
-        cost += area(&region) * sides(&region, &map);
+    return labels;
+}
+
+// A region has as many sides as corners. Each cell corner is convex when both cells next
+// to it along the edges are outside, and concave when both are inside but the diagonal one isn't.
+fn sides(cells: &[Pos], id: usize, labels: &Labels) -> usize {
+    let mut result = 0;
+    for pos in cells {
+        for (dx, dy) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+            let horizontal = labels.neighbour(pos, (dx, 0)) == Some(id);
+            let vertical = labels.neighbour(pos, (0, dy)) == Some(id);
+            let diagonal = labels.neighbour(pos, (dx, dy)) == Some(id);
+            if (!horizontal && !vertical) || (horizontal && vertical && !diagonal) {
+                result += 1;
+            }
+        }
     }
 
-    println!("Result2: {}", cost);
+    return result;
 }
 
-fn area(region: &Region) -> usize {
-    return region.len();
+struct Geometry {
+    area: usize,
+    perimeter: usize,
+    sides: usize,
+    // Top left and bottom right cells
+    bounds: (Pos, Pos),
+    holes: usize,
+    // Regions inside the holes, at any depth
+    nested: Vec<usize>,
 }
 
-fn perimeter(region: &Region, map: &Map) -> usize {
-    let mut result = 0;
-    for pos in region {
-        result += 4;
-
-        let movement = [(-1, 0), (1, 0), (0, -1), (0, 1)];
-        for next in movement {
-            if let Some(pos) = next_pos(&pos, map, next) {
-                if region.contains(&pos) {
-                    result -= 1;
+fn geometry(id: usize, labels: &Labels) -> Geometry {
+    let region = &labels.regions[id];
+    let (mut min, mut max) = (region.cells[0].clone(), region.cells[0].clone());
+    for pos in region.cells.iter() {
+        min = Pos {
+            x: min.x.min(pos.x),
+            y: min.y.min(pos.y),
+        };
+        max = Pos {
+            x: max.x.max(pos.x),
+            y: max.y.max(pos.y),
+        };
+    }
+
+    let (holes, nested) = holes(id, &min, &max, labels);
+    return Geometry {
+        area: region.area(),
+        perimeter: region.perimeter,
+        sides: sides(&region.cells, id, labels),
+        bounds: (min, max),
+        holes: holes,
+        nested: nested,
+    };
+}
+
+// Fill the cells of the bounding box outside of the region, any part that doesn't
+// reach the edge of the box is enclosed
+fn holes(id: usize, min: &Pos, max: &Pos, labels: &Labels) -> (usize, Vec<usize>) {
+    let movement = labels.connectivity.complement().deltas();
+    let inside = |pos: &Pos| pos.x >= min.x && pos.x <= max.x && pos.y >= min.y && pos.y <= max.y;
+
+    let mut seen = HashSet::new();
+    let mut holes = 0;
+    let mut enclosed_cells = BTreeMap::new();
+    for y in min.y..max.y + 1 {
+        for x in min.x..max.x + 1 {
+            let start = Pos { x: x, y: y };
+            if labels.get(&start) == id || seen.contains(&start) {
+                continue;
+            }
+
+            let mut enclosed = true;
+            let mut cells = Vec::new();
+            let mut pending = vec![start.clone()];
+            seen.insert(start);
+            while let Some(pos) = pending.pop() {
+                for delta in movement {
+                    match labels.neighbour(&pos, *delta) {
+                        Some(other) if other == id => {}
+                        _ => {
+                            let next = Pos {
+                                x: (pos.x as isize + delta.0 as isize) as usize,
+                                y: (pos.y as isize + delta.1 as isize) as usize,
+                            };
+                            if !inside(&next) {
+                                enclosed = false;
+                            } else if !seen.contains(&next) {
+                                seen.insert(next.clone());
+                                pending.push(next);
+                            }
+                        }
+                    }
+                }
+                cells.push(pos);
+            }
+
+            if enclosed {
+                holes += 1;
+                for pos in cells {
+                    *enclosed_cells.entry(labels.get(&pos)).or_insert(0) += 1;
                 }
             }
         }
     }
 
-    return result;
+    // With diagonal connections a region can go through the fence, so it is only nested
+    // when all of its cells are enclosed
+    let nested = enclosed_cells
+        .into_iter()
+        .filter(|(other, count)| labels.regions[*other].area() == *count)
+        .map(|(other, _)| other)
+        .collect();
+
+    return (holes, nested);
 }
 
-fn sides(region: &Region, map: &Map) -> usize {
-    let mut visited = HashSet::new();
-    let movement = [(-1, 0), (1, 0), (0, -1), (0, 1)];
-    let mut result = 0;
+// Closed loops of fence corners, the outer one first and then one per hole. Edges go
+// clockwise around the region, so it is always on their right.
+fn outline(id: usize, labels: &Labels) -> Vec<Vec<(usize, usize)>> {
+    let region = &labels.regions[id];
+    let mut edges = HashMap::<(usize, usize), Vec<(i32, i32)>>::new();
+    for pos in region.cells.iter() {
+        let (x, y) = (pos.x, pos.y);
+        let sides = [
+            ((0, -1), (x, y), (1, 0)),
+            ((1, 0), (x + 1, y), (0, 1)),
+            ((0, 1), (x + 1, y + 1), (-1, 0)),
+            ((-1, 0), (x, y + 1), (0, -1)),
+        ];
+        for (facing, start, direction) in sides {
+            if labels.neighbour(pos, facing) != Some(id) {
+                edges.entry(start).or_default().push(direction);
+            }
+        }
+    }
 
-    // For each direction
-    // 1. Find if direction is visible for pos
-    // 2. Find all joined pos that have this visible
-    // 3. Add to visited set
-    for visibility in movement {
-        for pos in region {
-            // If blocked, continue
-            if let Some(pos) = next_pos(&pos, map, visibility) {
-                if region.contains(&pos) {
-                    continue;
+    let mut starts = edges.keys().copied().collect::<Vec<_>>();
+    starts.sort_by_key(|(x, y)| (*y, *x));
+
+    let mut result = Vec::new();
+    for start in starts {
+        while edges.get(&start).is_some_and(|x| !x.is_empty()) {
+            let mut polygon = Vec::new();
+            let (mut point, mut direction) = (start, edges.get_mut(&start).unwrap().pop().unwrap());
+            loop {
+                polygon.push(point);
+                point = (
+                    (point.0 as i32 + direction.0) as usize,
+                    (point.1 as i32 + direction.1) as usize,
+                );
+
+                // Where the fence touches itself by a corner, turn right to stay on the same
+                // cell, or left to go on to the diagonal one when they are connected
+                let next = edges.get_mut(&point).unwrap();
+                let mut turns = [
+                    (-direction.1, direction.0),
+                    direction,
+                    (direction.1, -direction.0),
+                ];
+                if labels.connectivity == Connectivity::Eight {
+                    turns.reverse();
+                }
+                match turns.iter().find_map(|x| next.iter().position(|y| y == x)) {
+                    Some(index) => direction = next.swap_remove(index),
+                    None => break,
                 }
             }
 
-            // if already visited, continue
-            let entry = (pos.clone(), visibility);
-            if visited.contains(&entry) {
+            // Only keep the points where the fence turns
+            let count = polygon.len();
+            let corners = (0..count)
+                .filter(|i| {
+                    let (a, b, c) = (
+                        polygon[(i + count - 1) % count],
+                        polygon[*i],
+                        polygon[(i + 1) % count],
+                    );
+                    (b.0 as i32 - a.0 as i32, b.1 as i32 - a.1 as i32)
+                        != (c.0 as i32 - b.0 as i32, c.1 as i32 - b.1 as i32)
+                })
+                .map(|i| polygon[i])
+                .collect();
+            result.push(corners);
+        }
+    }
+
+    return result;
+}
+
+fn print_report(connectivity: Connectivity, outlines: bool) {
+    let map = input();
+    let labels = label(&map, connectivity);
+    for id in 0..labels.regions.len() {
+        let region = geometry(id, &labels);
+        let (min, max) = &region.bounds;
+        println!(
+            "{} {}: area {}, perimeter {}, sides {}, bounds ({},{})-({},{}), holes {}, nested {:?}",
+            id,
+            labels.regions[id].node,
+            region.area,
+            region.perimeter,
+            region.sides,
+            min.x,
+            min.y,
+            max.x,
+            max.y,
+            region.holes,
+            region.nested
+        );
+
+        if outlines {
+            for polygon in outline(id, &labels) {
+                println!("  {:?}", polygon);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Metric {
+    Area,
+    Perimeter,
+    Sides,
+    Width,
+    Height,
+    Holes,
+    Nested,
+}
+
+impl Metric {
+    fn parse(name: &str) -> Option<Metric> {
+        return match name {
+            "area" => Some(Metric::Area),
+            "perimeter" => Some(Metric::Perimeter),
+            "sides" => Some(Metric::Sides),
+            "width" => Some(Metric::Width),
+            "height" => Some(Metric::Height),
+            "holes" => Some(Metric::Holes),
+            "nested" => Some(Metric::Nested),
+            _ => None,
+        };
+    }
+
+    fn value(self, region: &Geometry) -> i64 {
+        let (min, max) = &region.bounds;
+        return match self {
+            Metric::Area => region.area as i64,
+            Metric::Perimeter => region.perimeter as i64,
+            Metric::Sides => region.sides as i64,
+            Metric::Width => (max.x - min.x + 1) as i64,
+            Metric::Height => (max.y - min.y + 1) as i64,
+            Metric::Holes => region.holes as i64,
+            Metric::Nested => region.nested.len() as i64,
+        };
+    }
+}
+
+// Cost of a region as an expression over its metrics, such as `area * sides - 10 * holes`
+enum Formula {
+    Constant(i64),
+    Metric(Metric),
+    Binary(char, Box<Formula>, Box<Formula>),
+}
+
+impl Formula {
+    fn parse(text: &str) -> Result<Formula, String> {
+        let mut tokens = Vec::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c.is_whitespace() {
                 continue;
             }
-            visited.insert(entry);
-
-            // Add new visibility line
-            result += 1;
-
-            // See where this can be visible, which is a 90 and - 90 rotation
-            for rotation in [1, -1] {
-                let direction = (visibility.1 * rotation, -visibility.0 * rotation);
-                let mut current = pos.clone();
-                while let Some(pos) = next_pos(&current, map, direction) {
-                    if !region.contains(&pos) {
-                        break;
-                    }
-                    // If there is a blocking view, break
-                    if let Some(viewing) = next_pos(&pos, map, visibility) {
-                        if region.contains(&viewing) {
-                            break;
-                        }
-                    }
-                    visited.insert((pos.clone(), visibility));
-                    current = pos.clone();
+
+            let mut token = c.to_string();
+            if c.is_ascii_alphanumeric() {
+                while let Some(next) = chars.next_if(|x| x.is_ascii_alphanumeric()) {
+                    token.push(next);
                 }
             }
+            tokens.push(token);
+        }
+
+        let mut pos = 0;
+        let formula = parse_sum(&tokens, &mut pos)?;
+        if pos < tokens.len() {
+            return Err(format!("unexpected '{}'", tokens[pos]));
         }
+
+        return Ok(formula);
     }
 
-    return result;
+    // None when the cost overflows or divides by zero
+    fn evaluate(&self, region: &Geometry) -> Option<i64> {
+        return match self {
+            Formula::Constant(value) => Some(*value),
+            Formula::Metric(metric) => Some(metric.value(region)),
+            Formula::Binary(op, a, b) => {
+                let (a, b) = (a.evaluate(region)?, b.evaluate(region)?);
+                match op {
+                    '+' => a.checked_add(b),
+                    '-' => a.checked_sub(b),
+                    '*' => a.checked_mul(b),
+                    _ => a.checked_div(b),
+                }
+            }
+        };
+    }
 }
 
-fn build_region(map: &Map, node: Node, pos: &Pos, result: &mut Region) {
-    if result.contains(&pos) {
-        return;
+// Operators of the same precedence are applied left to right
+fn parse_sum(tokens: &[String], pos: &mut usize) -> Result<Formula, String> {
+    let mut result = parse_product(tokens, pos)?;
+    while let Some(op) = tokens.get(*pos).filter(|x| *x == "+" || *x == "-") {
+        *pos += 1;
+        let op = op.chars().next().unwrap();
+        result = Formula::Binary(op, Box::new(result), Box::new(parse_product(tokens, pos)?));
     }
 
-    if map[pos.y as usize][pos.x as usize] != node {
-        return;
+    return Ok(result);
+}
+
+fn parse_product(tokens: &[String], pos: &mut usize) -> Result<Formula, String> {
+    let mut result = parse_term(tokens, pos)?;
+    while let Some(op) = tokens.get(*pos).filter(|x| *x == "*" || *x == "/") {
+        *pos += 1;
+        let op = op.chars().next().unwrap();
+        result = Formula::Binary(op, Box::new(result), Box::new(parse_term(tokens, pos)?));
     }
 
-    result.insert(pos.clone());
+    return Ok(result);
+}
+
+fn parse_term(tokens: &[String], pos: &mut usize) -> Result<Formula, String> {
+    let token = match tokens.get(*pos) {
+        Some(token) => token,
+        None => return Err("unexpected end of formula".to_string()),
+    };
+    *pos += 1;
 
-    let movement = [(-1, 0), (1, 0), (0, -1), (0, 1)];
-    for next in movement {
-        if let Some(pos) = next_pos(&pos, map, next) {
-            build_region(map, node, &pos, result);
+    if token == "(" {
+        let result = parse_sum(tokens, pos)?;
+        if tokens.get(*pos).is_none_or(|x| x != ")") {
+            return Err("missing ')'".to_string());
         }
+        *pos += 1;
+        return Ok(result);
+    }
+
+    if let Ok(value) = token.parse() {
+        return Ok(Formula::Constant(value));
+    }
+
+    return match Metric::parse(token) {
+        Some(metric) => Ok(Formula::Metric(metric)),
+        None => Err(format!("unknown metric '{}'", token)),
+    };
+}
+
+// Total cost of fencing every region, None if any cost can't be computed
+fn price(labels: &Labels, cost: &dyn Fn(&Geometry) -> Option<i64>) -> Option<i64> {
+    let mut total = 0i64;
+    for id in 0..labels.regions.len() {
+        total = total.checked_add(cost(&geometry(id, labels))?)?;
+    }
+
+    return Some(total);
+}
+
+fn print_price(formula: &Formula, connectivity: Connectivity) {
+    let map = input();
+    let labels = label(&map, connectivity);
+    match price(&labels, &|x| formula.evaluate(x)) {
+        Some(total) => println!("Price: {}", total),
+        None => println!("Price: overflow or division by zero"),
     }
 }
 
@@ -173,3 +566,101 @@ fn next_pos(pos: &Pos, map: &Map, (x, y): (i32, i32)) -> Option<Pos> {
         y: y as usize,
     });
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(text: &str) -> Map {
+        return text.lines().map(|x| x.trim().chars().collect()).collect();
+    }
+
+    // Sides found by walking along each fence line, as the corner count replaced
+    fn sides_walked(cells: &[Pos], id: usize, labels: &Labels, map: &Map) -> usize {
+        let mut visited = HashSet::new();
+        let mut result = 0;
+        for visibility in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            for pos in cells {
+                if let Some(pos) = next_pos(pos, map, visibility) {
+                    if labels.get(&pos) == id {
+                        continue;
+                    }
+                }
+                if !visited.insert((pos.clone(), visibility)) {
+                    continue;
+                }
+                result += 1;
+
+                for rotation in [1, -1] {
+                    let direction = (visibility.1 * rotation, -visibility.0 * rotation);
+                    let mut current = pos.clone();
+                    while let Some(pos) = next_pos(&current, map, direction) {
+                        if labels.get(&pos) != id {
+                            break;
+                        }
+                        if let Some(viewing) = next_pos(&pos, map, visibility) {
+                            if labels.get(&viewing) == id {
+                                break;
+                            }
+                        }
+                        visited.insert((pos.clone(), visibility));
+                        current = pos;
+                    }
+                }
+            }
+        }
+
+        return result;
+    }
+
+    #[test]
+    fn test_sides() {
+        let maps = [
+            "AAAA\nBBCD\nBBCC\nEEEC",
+            "OOOOO\nOXOXO\nOOOOO\nOXOXO\nOOOOO",
+            "EEEEE\nEXXXX\nEEEEE\nEXXXX\nEEEEE",
+            "AAAAAA\nAAABBA\nAAABBA\nABBAAA\nABBAAA\nAAAAAA",
+            "ABA\nBAB\nABA",
+        ];
+        for text in maps {
+            let map = parse(text);
+            let labels = label(&map, Connectivity::Four);
+            for (id, region) in labels.regions.iter().enumerate() {
+                let sides = sides(&region.cells, id, &labels);
+                assert_eq!(sides_walked(&region.cells, id, &labels, &map), sides);
+
+                // Each side of the fence ends at a corner of the outline
+                let corners = outline(id, &labels).iter().map(|x| x.len()).sum::<usize>();
+                assert_eq!(sides, corners, "{} region {}", text, id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_holes() {
+        let map = parse("AAAAAAA\nABBBBBA\nABCCCBA\nABCDCBA\nABCCCBA\nABBBBBA\nAAAAAAA");
+        let labels = label(&map, Connectivity::Four);
+        let regions = (0..labels.regions.len())
+            .map(|x| geometry(x, &labels))
+            .collect::<Vec<_>>();
+
+        let ids = [(0, 0), (1, 1), (2, 2), (3, 3)].map(|(x, y)| labels.get(&Pos { x: x, y: y }));
+        assert_eq!(1, regions[ids[0]].holes);
+        assert_eq!(vec![ids[1], ids[2], ids[3]], regions[ids[0]].nested);
+        assert_eq!(vec![ids[2], ids[3]], regions[ids[1]].nested);
+        assert_eq!(vec![ids[3]], regions[ids[2]].nested);
+        assert_eq!(0, regions[ids[3]].holes);
+        assert_eq!(2, outline(ids[1], &labels).len());
+
+        // The outside uses the other connectivity. With 4-connected regions the two B cells
+        // touching by a corner are one hole in A, with 8-connected regions A passes between
+        // them, so they are one region in two holes.
+        let map = parse("AAAA\nABAA\nAABA\nAAAA");
+        let labels = label(&map, Connectivity::Four);
+        assert_eq!(1, geometry(0, &labels).holes);
+        assert_eq!(3, labels.regions.len());
+        let labels = label(&map, Connectivity::Eight);
+        assert_eq!(2, geometry(0, &labels).holes);
+        assert_eq!(vec![1], geometry(0, &labels).nested);
+    }
+}
@@ -5,73 +5,320 @@ const INPUT: &str = include_str!("input/n3.input");
 fn main() {
     p1();
     p2();
+
+    if std::env::args().any(|x| x == "--extended") {
+        p3();
+    }
+
+    if std::env::args().any(|x| x == "--trace") {
+        print_trace();
+    }
 }
 
 fn p1() {
-    let mut total = 0;
+    let mut set = InstructionSet::new();
+    set.register("mul", 2, execute_mul);
 
-    let re = Regex::new("(?<valid>mul\\([0-9]{1,3},[0-9]{1,3}\\))").unwrap();
+    let state = execute(&set.scan(INPUT), State::default());
+    println!("Total 1: {}", state.total);
+}
 
-    let result = re.captures_iter(INPUT);
-    for i in result {
-        let val = i.name("valid").unwrap().as_str();
-        total += execute_mul(val);
+fn p2() {
+    let mut set = InstructionSet::new();
+    set.register("mul", 2, execute_mul);
+    set.register("do", 0, execute_do);
+    set.register("don't", 0, execute_dont);
+
+    let state = execute(&set.scan(INPUT), State::default());
+    println!("Total 2: {}", state.total);
+}
+
+fn p3() {
+    let mut set = InstructionSet::new();
+    set.register("mul", 2, execute_mul);
+    set.register("add", 2, execute_add);
+    set.register("neg", 1, execute_neg);
+    set.register("do", 0, execute_do);
+    set.register("don't", 0, execute_dont);
+    set.register("toggle", 0, execute_toggle);
+
+    let state = execute(&set.scan(INPUT), State::default());
+    println!("Total 3: {}", state.total);
+}
+
+// Accumulator shared by all the handlers while running the instructions
+#[derive(Clone, Debug)]
+struct State {
+    total: i64,
+    active: bool,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            total: 0,
+            active: true,
+        }
     }
-    println!("Total 1: {}", total);
 }
 
-fn execute_mul(mul: &str) -> i32 {
-    let re = Regex::new("mul\\((?<a>[0-9]{1,3}),(?<b>[0-9]{1,3})\\)").unwrap();
-    let result = re.captures(mul).unwrap();
+type Handler = fn(&mut State, &[i32]);
 
-    return result.name("a").unwrap().as_str().parse::<i32>().unwrap()
-        * result.name("b").unwrap().as_str().parse::<i32>().unwrap();
+struct Definition {
+    name: &'static str,
+    arity: usize,
+    handler: Handler,
 }
 
-fn p2() {
-    let mut total = 0;
-
-    let re =
-        Regex::new("(?<valid>(mul\\([0-9]{1,3},[0-9]{1,3}\\))|(do\\(\\))|(don't\\(\\)))").unwrap();
-
-    let result = re.captures_iter(INPUT);
-    let mut active = true;
-    for i in result {
-        let val = i.name("valid").unwrap().as_str();
-        let ins = parse(val);
-        match ins {
-            Instruction::DO => active = true,
-            Instruction::DONT => active = false,
-            Instruction::MUL(a, b) => {
-                if active {
-                    total += a * b;
+struct Instruction<'a> {
+    definition: &'a Definition,
+    args: Vec<i32>,
+    offset: usize,
+    text: &'a str,
+}
+
+// Fragment that looks like an instruction call but was not accepted by the scanner
+struct Rejection<'a> {
+    offset: usize,
+    text: &'a str,
+    reason: String,
+}
+
+struct TraceStep<'a> {
+    offset: usize,
+    text: &'a str,
+    active: bool,
+    total: i64,
+}
+
+struct InstructionSet {
+    definitions: Vec<Definition>,
+}
+
+impl InstructionSet {
+    fn new() -> InstructionSet {
+        InstructionSet {
+            definitions: Vec::new(),
+        }
+    }
+
+    fn register(&mut self, name: &'static str, arity: usize, handler: Handler) {
+        self.definitions.push(Definition {
+            name: name,
+            arity: arity,
+            handler: handler,
+        });
+    }
+
+    // Regex matching any of the registered instructions with exactly their arity
+    fn pattern(&self) -> Regex {
+        let mut definitions = self.definitions.iter().collect::<Vec<_>>();
+        // Longest names first, so a name is not shadowed by one of its prefixes
+        definitions.sort_by_key(|x| std::cmp::Reverse(x.name.len()));
+
+        let alternatives = definitions
+            .iter()
+            .map(|x| {
+                let args = vec!["[0-9]{1,3}"; x.arity].join(",");
+                format!("{}\\({}\\)", regex::escape(x.name), args)
+            })
+            .collect::<Vec<_>>();
+
+        return Regex::new(&alternatives.join("|")).unwrap();
+    }
+
+    fn scan<'a>(&'a self, memory: &'a str) -> Vec<Instruction<'a>> {
+        let mut result = Vec::new();
+        for m in self.pattern().find_iter(memory) {
+            let (name, args) = m.as_str().split_once("(").unwrap();
+            let definition = self.definitions.iter().find(|x| x.name == name).unwrap();
+            let args = args
+                .trim_end_matches(")")
+                .split(",")
+                .filter(|x| !x.is_empty())
+                .map(|x| x.parse().unwrap())
+                .collect();
+
+            result.push(Instruction {
+                definition: definition,
+                args: args,
+                offset: m.start(),
+                text: m.as_str(),
+            });
+        }
+
+        return result;
+    }
+
+    // Find calls of registered names that the scanner did not accept, such as `mul(4*` or `mul ( 2 , 4 )`
+    fn near_misses<'a>(&self, memory: &'a str, accepted: &[Instruction]) -> Vec<Rejection<'a>> {
+        let mut result = Vec::new();
+        for definition in self.definitions.iter() {
+            let re = Regex::new(&format!("{}\\s*\\(", regex::escape(definition.name))).unwrap();
+            for m in re.find_iter(memory) {
+                if accepted.iter().any(|x| x.offset == m.start()) {
+                    continue;
+                }
+
+                // Extend the fragment up to the closing parenthesis or the first unexpected character
+                let mut end = m.end();
+                for c in memory[m.end()..].chars().take(16) {
+                    end += c.len_utf8();
+                    if !(c.is_ascii_digit() || c == ',' || c.is_whitespace()) {
+                        break;
+                    }
+                }
+
+                let text = &memory[m.start()..end];
+                if let Some(reason) = reject_reason(definition, text) {
+                    result.push(Rejection {
+                        offset: m.start(),
+                        text: text,
+                        reason: reason,
+                    });
                 }
             }
         }
+
+        result.sort_by_key(|x| x.offset);
+        return result;
     }
-    println!("Total 2: {}", total);
 }
 
-enum Instruction {
-    DO,
-    DONT,
-    MUL(i32, i32),
+fn reject_reason(definition: &Definition, text: &str) -> Option<String> {
+    let (name, args) = text.split_once("(").unwrap();
+    if name.len() != definition.name.len() {
+        return Some("whitespace before '('".to_string());
+    }
+
+    let args = match args.strip_suffix(")") {
+        Some(args) => args,
+        None => match args.chars().last() {
+            Some(c) if !(c.is_ascii_digit() || c == ',' || c.is_whitespace()) => {
+                return Some(format!("unexpected character '{}'", c.escape_debug()))
+            }
+            _ => return Some("missing ')'".to_string()),
+        },
+    };
+
+    if args.chars().any(|x| x.is_whitespace()) {
+        return Some("whitespace inside arguments".to_string());
+    }
+
+    // `mul()` has no arguments, but `mul(2,)` has an empty one
+    let args = match args {
+        "" => Vec::new(),
+        args => args.split(",").collect::<Vec<_>>(),
+    };
+    if args.len() != definition.arity {
+        return Some(format!(
+            "expected {} arguments, found {}",
+            definition.arity,
+            args.len()
+        ));
+    }
+
+    if args.iter().any(|x| x.is_empty()) {
+        return Some("empty argument".to_string());
+    }
+
+    if let Some(arg) = args.iter().find(|x| x.len() > 3) {
+        return Some(format!("argument {} has more than 3 digits", arg));
+    }
+
+    return None;
 }
 
-fn parse(instruction: &str) -> Instruction {
-    let re = Regex::new("(?<instruction>[a-z']+)\\((?<a>([0-9]{1,3}))?,?(?<b>([0-9]{1,3}))?\\)")
-        .unwrap();
-    let result = re.captures(instruction).unwrap();
+fn execute(instructions: &[Instruction], mut state: State) -> State {
+    for ins in instructions {
+        (ins.definition.handler)(&mut state, &ins.args);
+    }
 
-    match result.name("instruction").unwrap().as_str() {
-        "do" => return Instruction::DO,
-        "don't" => return Instruction::DONT,
-        "mul" => {
-            return Instruction::MUL(
-                result.name("a").unwrap().as_str().parse().unwrap(),
-                result.name("b").unwrap().as_str().parse().unwrap(),
-            )
-        }
-        _ => unreachable!(""),
+    return state;
+}
+
+fn trace<'a>(instructions: &[Instruction<'a>], mut state: State) -> Vec<TraceStep<'a>> {
+    let mut result = Vec::new();
+    for ins in instructions {
+        let active = state.active;
+        (ins.definition.handler)(&mut state, &ins.args);
+        result.push(TraceStep {
+            offset: ins.offset,
+            text: ins.text,
+            active: active,
+            total: state.total,
+        });
     }
+
+    return result;
+}
+
+fn print_trace() {
+    let mut set = InstructionSet::new();
+    set.register("mul", 2, execute_mul);
+    set.register("do", 0, execute_do);
+    set.register("don't", 0, execute_dont);
+
+    let instructions = set.scan(INPUT);
+    let steps = trace(&instructions, State::default());
+    let rejections = set.near_misses(INPUT, &instructions);
+
+    // Merge both lists by offset
+    let mut lines = Vec::new();
+    for step in steps {
+        let status = if step.active { "active" } else { "skipped" };
+        lines.push((
+            step.offset,
+            format!(
+                "{:>8}  {:<16} {:<8} total={}",
+                step.offset, step.text, status, step.total
+            ),
+        ));
+    }
+    for rejection in rejections {
+        lines.push((
+            rejection.offset,
+            format!(
+                "{:>8}  {:<16} rejected: {}",
+                rejection.offset,
+                rejection.text.escape_debug().to_string(),
+                rejection.reason
+            ),
+        ));
+    }
+    lines.sort_by_key(|x| x.0);
+
+    for (_, line) in lines {
+        println!("{}", line);
+    }
+}
+
+fn execute_mul(state: &mut State, args: &[i32]) {
+    if state.active {
+        state.total += args[0] as i64 * args[1] as i64;
+    }
+}
+
+fn execute_add(state: &mut State, args: &[i32]) {
+    if state.active {
+        state.total += args[0] as i64 + args[1] as i64;
+    }
+}
+
+fn execute_neg(state: &mut State, args: &[i32]) {
+    if state.active {
+        state.total -= args[0] as i64;
+    }
+}
+
+fn execute_do(state: &mut State, _: &[i32]) {
+    state.active = true;
+}
+
+fn execute_dont(state: &mut State, _: &[i32]) {
+    state.active = false;
+}
+
+fn execute_toggle(state: &mut State, _: &[i32]) {
+    state.active = !state.active;
 }
@@ -1,128 +1,290 @@
+use std::collections::HashMap;
+
 const INPUT: &str = include_str!("input/n4.input");
 
+type Map = Vec<Vec<char>>;
+
+#[derive(Clone, Copy, Debug)]
+struct Direction(isize, isize);
+
+const DIRECTIONS: [Direction; 8] = [
+    Direction(-1, -1),
+    Direction(-1, 0),
+    Direction(-1, 1),
+    Direction(0, -1),
+    Direction(0, 1),
+    Direction(1, -1),
+    Direction(1, 0),
+    Direction(1, 1),
+];
+
+#[derive(Debug)]
+struct Match {
+    word: usize,
+    x: usize,
+    y: usize,
+    direction: Direction,
+}
+
+// Prefix tree of the searched words, so a single walk from each cell checks all of them at once
+struct Trie {
+    nodes: Vec<TrieNode>,
+}
+
+#[derive(Default)]
+struct TrieNode {
+    children: HashMap<char, usize>,
+    word: Option<usize>,
+}
+
+impl Trie {
+    fn new(words: &[&str]) -> Trie {
+        let mut trie = Trie {
+            nodes: vec![TrieNode::default()],
+        };
+
+        for (i, word) in words.iter().enumerate() {
+            let mut node = 0;
+            for c in word.chars() {
+                node = match trie.nodes[node].children.get(&c) {
+                    Some(next) => *next,
+                    None => {
+                        trie.nodes.push(TrieNode::default());
+                        let next = trie.nodes.len() - 1;
+                        trie.nodes[node].children.insert(c, next);
+                        next
+                    }
+                };
+            }
+            trie.nodes[node].word = Some(i);
+        }
+
+        return trie;
+    }
+}
+
 fn main() {
     p1();
     p2();
+
+    let args = std::env::args().collect::<Vec<_>>();
+    if let Some(pos) = args.iter().position(|x| x == "--words") {
+        print_matches(&args[pos + 1].split(",").collect::<Vec<_>>());
+    }
+    if let Some(pos) = args.iter().position(|x| x == "--template") {
+        print_placements(&args[pos + 1]);
+    }
 }
 
-type Map = Vec<Vec<Code>>;
+fn p1() {
+    let map = load();
+    let count = search(&map, &["XMAS"]).len();
 
-#[derive(PartialEq)]
-enum Code {
-    X,
-    M,
-    A,
-    S,
+    println!("Count1: {}", count);
 }
 
-struct Direction(isize, isize);
-
-fn p1() {
+fn print_matches(words: &[&str]) {
     let map = load();
-    let mut count = 0;
+    for m in search(&map, words) {
+        println!(
+            "{} at ({}, {}) towards {:?}",
+            words[m.word], m.x, m.y, m.direction
+        );
+    }
+}
+
+// Find every word of the list in all eight directions
+fn search(map: &Map, words: &[&str]) -> Vec<Match> {
+    let trie = Trie::new(words);
+    let mut result = Vec::new();
 
     for x in 0..map.len() {
         for y in 0..map[x].len() {
-            for a in [-1, 0, 1] {
-                for b in [-1, 0, 1] {
-                    // skip not moving
-                    if a == 0 && b == 0 {
-                        continue;
-                    }
-                    if search_pos(
-                        &map,
-                        x as isize,
-                        y as isize,
-                        &[Code::X, Code::M, Code::A, Code::S],
-                        &Direction(a, b),
-                    ) {
-                        count += 1;
+            for direction in DIRECTIONS {
+                let (mut cx, mut cy) = (x as isize, y as isize);
+                let mut node = 0;
+                while cx >= 0
+                    && cy >= 0
+                    && (cx as usize) < map.len()
+                    && (cy as usize) < map[cx as usize].len()
+                {
+                    node = match trie.nodes[node]
+                        .children
+                        .get(&map[cx as usize][cy as usize])
+                    {
+                        Some(next) => *next,
+                        None => break,
+                    };
+
+                    if let Some(word) = trie.nodes[node].word {
+                        result.push(Match {
+                            word: word,
+                            x: x,
+                            y: y,
+                            direction: direction,
+                        });
                     }
+
+                    cx += direction.0;
+                    cy += direction.1;
                 }
             }
         }
     }
 
-    println!("Count1: {}", count);
+    return result;
 }
 
-fn search_pos(map: &Map, x: isize, y: isize, left: &[Code], direction: &Direction) -> bool {
-    if x as usize >= map.len() || y as usize >= map[x as usize].len() {
-        return false;
+fn load() -> Map {
+    INPUT.lines().map(|line| line.chars().collect()).collect()
+}
+
+fn p2() {
+    let map = load();
+    let count = match_template(&map, &Template::parse(X_MAS), true).len();
+
+    println!("Count2: {}", count);
+}
+
+// Rows of a template, `.` matches any letter
+const X_MAS: &str = "M.S\n.A.\nM.S";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Template {
+    cells: Vec<Vec<Option<char>>>,
+}
+
+#[derive(Debug)]
+struct Placement {
+    x: usize,
+    y: usize,
+    variant: usize,
+}
+
+impl Template {
+    fn parse(pattern: &str) -> Template {
+        let cells = pattern
+            .lines()
+            .map(|line| {
+                line.chars()
+                    .map(|c| match c {
+                        '.' => None,
+                        c => Some(c),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        return Template { cells: cells };
     }
 
-    if map[x as usize][y as usize] != left[0] {
-        return false;
+    fn height(&self) -> usize {
+        return self.cells.len();
     }
 
-    if left.len() == 1 {
-        return true;
+    fn width(&self) -> usize {
+        return self.cells.iter().map(|x| x.len()).max().unwrap_or(0);
     }
 
-    let (x, y) = (x + direction.0, y + direction.1);
-    if x < 0 || y < 0 {
-        return false;
+    fn get(&self, x: usize, y: usize) -> Option<char> {
+        return self.cells[x].get(y).copied().flatten();
     }
 
-    return search_pos(map, x, y, &left[1..], direction);
-}
+    // Rotate 90 degrees clockwise
+    fn rotate(&self) -> Template {
+        let (height, width) = (self.height(), self.width());
+        let cells = (0..width)
+            .map(|x| (0..height).map(|y| self.get(height - 1 - y, x)).collect())
+            .collect();
 
-fn load() -> Vec<Vec<Code>> {
-    INPUT
-        .lines()
-        .map(|line| {
-            line.chars()
-                .map(|x| match x {
-                    'X' => Code::X,
-                    'M' => Code::M,
-                    'A' => Code::A,
-                    'S' => Code::S,
-                    _ => unreachable!(),
-                })
-                .collect()
-        })
-        .collect()
-}
+        return Template { cells: cells };
+    }
 
-fn p2() {
-    let map = load();
-    let mut count = 0;
+    // Mirror left to right
+    fn reflect(&self) -> Template {
+        let width = self.width();
+        let cells = (0..self.height())
+            .map(|x| (0..width).map(|y| self.get(x, width - 1 - y)).collect())
+            .collect();
 
-    for x in 0..map.len() {
-        for y in 0..map[x].len() {
-            if search_mas(&map, x as isize, y as isize) {
-                count += 1;
+        return Template { cells: cells };
+    }
+
+    // Distinct templates from all the rotations and reflections
+    fn variants(&self) -> Vec<Template> {
+        let mut result = Vec::<Template>::new();
+        let mut current = self.clone();
+        for _ in 0..4 {
+            for variant in [current.clone(), current.reflect()] {
+                if !result.contains(&variant) {
+                    result.push(variant);
+                }
             }
+            current = current.rotate();
         }
+
+        return result;
     }
 
-    println!("Count2: {}", count);
-}
+    // The whole box of the template has to be in the map, wildcards included,
+    // so every rotation and reflection can be placed in the same places
+    fn matches(&self, map: &Map, x: usize, y: usize) -> bool {
+        if x + self.height() > map.len()
+            || (x..x + self.height()).any(|row| y + self.width() > map[row].len())
+        {
+            return false;
+        }
+
+        for dx in 0..self.height() {
+            for dy in 0..self.cells[dx].len() {
+                let expected = match self.cells[dx][dy] {
+                    None => continue,
+                    Some(c) => c,
+                };
 
-fn search_mas(map: &Map, x: isize, y: isize) -> bool {
-    // Find MAS going down
-    if !search_pos(map, x, y, &[Code::M, Code::A, Code::S], &Direction(1, 1))
-        && !search_pos(map, x, y, &[Code::S, Code::A, Code::M], &Direction(1, 1))
-    {
-        return false;
+                match map.get(x + dx).and_then(|row| row.get(y + dy)) {
+                    Some(c) if *c == expected => continue,
+                    _ => return false,
+                }
+            }
+        }
+
+        return true;
     }
+}
+
+// Every placement of the template in the map, the variant is the index in `Template::variants`
+fn match_template(map: &Map, template: &Template, transform: bool) -> Vec<Placement> {
+    let variants = match transform {
+        true => template.variants(),
+        false => vec![template.clone()],
+    };
 
-    // Find MAS Down two going up
-    if !search_pos(
-        map,
-        x + 2,
-        y,
-        &[Code::M, Code::A, Code::S],
-        &Direction(-1, 1),
-    ) && !search_pos(
-        map,
-        x + 2,
-        y,
-        &[Code::S, Code::A, Code::M],
-        &Direction(-1, 1),
-    ) {
-        return false;
+    let mut result = Vec::new();
+    for (variant, template) in variants.iter().enumerate() {
+        for x in 0..map.len() {
+            for y in 0..map[x].len() {
+                if template.matches(map, x, y) {
+                    result.push(Placement {
+                        x: x,
+                        y: y,
+                        variant: variant,
+                    });
+                }
+            }
+        }
     }
 
-    return true;
+    return result;
+}
+
+fn print_placements(pattern: &str) {
+    let map = load();
+    let template = Template::parse(&pattern.replace("/", "\n"));
+    let variants = template.variants();
+    let placements = match_template(&map, &template, true);
+    for p in placements.iter() {
+        println!("({}, {}) as {:?}", p.x, p.y, variants[p.variant].cells);
+    }
+    println!("Placements: {}", placements.len());
 }
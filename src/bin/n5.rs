@@ -1,59 +1,286 @@
-use std::cmp::Ordering;
-use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
 
 const INPUT: &str = include_str!("input/n5.input");
 
 fn main() {
     p1();
     p2();
+
+    if std::env::args().any(|x| x == "--report") {
+        report();
+    }
 }
 
 fn p1() {
     let (rules, total_pages) = load();
+    let graph = Graph::new(&rules);
     let mut result = 0;
     for pages in total_pages {
-        let mut valid = true;
-        'outer: for page in &pages {
-            for rule in rules.get(&page).unwrap_or(&Vec::default()) {
-                // Check that all rules match in this
-                if !match_rules(&pages, rule) {
-                    valid = false;
-                    break 'outer;
+        if graph.is_valid(&pages) {
+            let middle = pages[pages.len() / 2];
+            result += middle;
+        }
+    }
+
+    println!("Result1: {}", result);
+}
+
+struct Rule {
+    page: i32,
+    before: i32,
+}
+
+// Chain of rules `X|Y` that loop back to the first page
+#[derive(Debug)]
+struct Cycle {
+    chain: Vec<i32>,
+}
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rules = self
+            .chain
+            .iter()
+            .zip(self.chain.iter().cycle().skip(1))
+            .map(|(a, b)| format!("{}|{}", a, b))
+            .collect::<Vec<_>>();
+        return write!(f, "{}", rules.join(" -> "));
+    }
+}
+
+struct Violation {
+    page: i32,
+    before: i32,
+    page_pos: usize,
+    before_pos: usize,
+}
+
+struct Move {
+    page: i32,
+    from: usize,
+    to: usize,
+}
+
+// Rules as edges from each page to the pages that must be printed after it
+struct Graph {
+    edges: HashMap<i32, Vec<i32>>,
+}
+
+impl Graph {
+    fn new(rules: &[Rule]) -> Graph {
+        let mut edges = HashMap::new();
+        for rule in rules {
+            edges
+                .entry(rule.page)
+                .or_insert(Vec::new())
+                .push(rule.before);
+        }
+
+        return Graph { edges: edges };
+    }
+
+    fn after(&self, page: i32) -> &[i32] {
+        return self.edges.get(&page).map(|x| x.as_slice()).unwrap_or(&[]);
+    }
+
+    // Check that no rule between the pages of the update is broken
+    fn is_valid(&self, pages: &[i32]) -> bool {
+        let positions = positions(pages);
+        for (pos, page) in pages.iter().enumerate() {
+            for before in self.after(*page) {
+                if let Some(other) = positions.get(before) {
+                    if *other < pos {
+                        return false;
+                    }
                 }
             }
         }
 
-        if valid {
-            let middle = pages[pages.len() / 2];
-            result += middle;
+        return true;
+    }
+
+    // Broken rules `X|Y` where Y is printed before X
+    fn violations(&self, pages: &[i32]) -> Vec<Violation> {
+        let positions = positions(pages);
+        let mut result = Vec::new();
+        for (pos, page) in pages.iter().enumerate() {
+            for before in self.after(*page) {
+                if let Some(other) = positions.get(before) {
+                    if *other < pos {
+                        result.push(Violation {
+                            page: *page,
+                            before: *before,
+                            page_pos: pos,
+                            before_pos: *other,
+                        });
+                    }
+                }
+            }
         }
+
+        return result;
     }
 
-    println!("Result1: {}", result);
+    // Topological order of the pages, only using the rules between them.
+    // Pages without constraints keep their original relative order.
+    fn order(&self, pages: &[i32]) -> Result<Vec<i32>, Cycle> {
+        let positions = positions(pages);
+        let mut incoming = HashMap::<i32, usize>::new();
+        for page in pages {
+            for before in self.after(*page) {
+                if positions.contains_key(before) {
+                    *incoming.entry(*before).or_insert(0) += 1;
+                }
+            }
+        }
+
+        let mut ready = pages
+            .iter()
+            .filter(|x| !incoming.contains_key(x))
+            .copied()
+            .collect::<VecDeque<_>>();
+        let mut result = Vec::with_capacity(pages.len());
+        while let Some(page) = ready.pop_front() {
+            result.push(page);
+            for before in self.after(page) {
+                if let Some(count) = incoming.get_mut(before) {
+                    *count -= 1;
+                    if *count == 0 {
+                        incoming.remove(before);
+                        ready.push_back(*before);
+                    }
+                }
+            }
+        }
+
+        if !incoming.is_empty() {
+            return Err(self.find_cycle(&incoming));
+        }
+
+        return Ok(result);
+    }
+
+    // Every remaining page still has a remaining page before it, so walking
+    // backwards through them must eventually repeat a page
+    fn find_cycle(&self, remaining: &HashMap<i32, usize>) -> Cycle {
+        let mut previous = HashMap::new();
+        for page in remaining.keys() {
+            for before in self.after(*page) {
+                if remaining.contains_key(before) {
+                    previous.entry(*before).or_insert(*page);
+                }
+            }
+        }
+
+        let mut page = *remaining.keys().min().unwrap();
+        let mut path = Vec::new();
+        let mut seen = HashSet::new();
+        while seen.insert(page) {
+            path.push(page);
+            page = previous[&page];
+        }
+
+        let start = path.iter().position(|x| *x == page).unwrap();
+        let mut chain = path[start..].to_vec();
+        chain.reverse();
+
+        // Start from the lowest page, so the same cycle is always reported the same way
+        let lowest = (0..chain.len()).min_by_key(|x| chain[*x]).unwrap();
+        chain.rotate_left(lowest);
+        return Cycle { chain: chain };
+    }
 }
 
-fn match_rules(pages: &Vec<i32>, rule: &Rule) -> bool {
-    for page in pages {
-        if *page == rule.page {
-            return true;
+// Pages to move to get the corrected order. The pages that stay in place are
+// the longest run of pages already in the corrected relative order. Moves are
+// sorted by target, so the page each one goes after is already in its place.
+fn minimal_moves(pages: &[i32], corrected: &[i32]) -> Vec<Move> {
+    let target = positions(corrected);
+    let order = pages.iter().map(|x| target[x]).collect::<Vec<_>>();
+
+    // Longest increasing subsequence of the target positions
+    let mut length = vec![1; order.len()];
+    let mut previous = vec![None; order.len()];
+    for i in 0..order.len() {
+        for j in 0..i {
+            if order[j] < order[i] && length[j] + 1 > length[i] {
+                length[i] = length[j] + 1;
+                previous[i] = Some(j);
+            }
         }
+    }
+
+    let mut keep = HashSet::new();
+    let mut current = (0..order.len()).max_by_key(|x| length[*x]);
+    while let Some(i) = current {
+        keep.insert(i);
+        current = previous[i];
+    }
+
+    let mut moves = (0..pages.len())
+        .filter(|x| !keep.contains(x))
+        .map(|x| Move {
+            page: pages[x],
+            from: x,
+            to: order[x],
+        })
+        .collect::<Vec<_>>();
+    moves.sort_by_key(|x| x.to);
 
-        if *page == rule.before {
-            return false;
+    return moves;
+}
+
+fn report() {
+    let (rules, total_pages) = load();
+    let graph = Graph::new(&rules);
+
+    for pages in total_pages {
+        let violations = graph.violations(&pages);
+        if violations.is_empty() {
+            println!("{:?}: valid", pages);
+            continue;
+        }
+
+        println!("{:?}: invalid", pages);
+        for v in violations {
+            println!(
+                "  breaks {}|{}: {} at {}, {} at {}",
+                v.page, v.before, v.page, v.page_pos, v.before, v.before_pos
+            );
+        }
+
+        match graph.order(&pages) {
+            Ok(corrected) => {
+                println!("  corrected {:?}", corrected);
+                for m in minimal_moves(&pages, &corrected) {
+                    match m.to {
+                        0 => println!("  move {} from {} to the start", m.page, m.from),
+                        to => println!(
+                            "  move {} from {} to after {}",
+                            m.page,
+                            m.from,
+                            corrected[to - 1]
+                        ),
+                    }
+                }
+            }
+            Err(cycle) => println!("  cannot be fixed, cycle {}", cycle),
         }
     }
-    unreachable!()
 }
 
-struct Rule {
-    page: i32,
-    before: i32,
+fn positions(pages: &[i32]) -> HashMap<i32, usize> {
+    return pages
+        .iter()
+        .enumerate()
+        .map(|(pos, page)| (*page, pos))
+        .collect();
 }
 
-fn load() -> (HashMap<i32, Vec<Rule>>, Vec<Vec<i32>>) {
+fn load() -> (Vec<Rule>, Vec<Vec<i32>>) {
     // X|Y = (page number)|()
-    let mut rules = HashMap::new();
+    let mut rules = Vec::new();
     let mut pages = Vec::new();
     let mut on_rules = true;
     for line in INPUT.lines() {
@@ -67,18 +294,10 @@ fn load() -> (HashMap<i32, Vec<Rule>>, Vec<Vec<i32>>) {
                 .map(|x| x.parse().unwrap())
                 .collect::<Vec<i32>>();
 
-            let rule = Rule {
+            rules.push(Rule {
                 page: result[0],
                 before: result[1],
-            };
-            match rules.entry(rule.page) {
-                Entry::Vacant(e) => {
-                    e.insert(vec![rule]);
-                }
-                Entry::Occupied(mut e) => {
-                    e.get_mut().push(rule);
-                }
-            };
+            });
         } else {
             let result = line.split(",").map(|x| x.parse().unwrap()).collect();
             pages.push(result);
@@ -89,41 +308,90 @@ fn load() -> (HashMap<i32, Vec<Rule>>, Vec<Vec<i32>>) {
 }
 
 fn p2() {
-    let (rules, mut total_pages) = load();
+    let (rules, total_pages) = load();
+    let graph = Graph::new(&rules);
     let mut result = 0;
 
-    for pages in total_pages.iter_mut() {
-        'outer: for page in &pages.clone() {
-            for rule in rules.get(&page).unwrap_or(&Vec::default()) {
-                // Check that all rules match in this
-                if !match_rules(&pages, rule) {
-                    sort(pages, &rules);
+    for pages in total_pages {
+        if graph.is_valid(&pages) {
+            continue;
+        }
 
-                    let middle = pages[pages.len() / 2];
-                    result += middle;
-                    break 'outer;
-                }
+        match graph.order(&pages) {
+            Ok(pages) => {
+                let middle = pages[pages.len() / 2];
+                result += middle;
             }
+            Err(cycle) => println!("Cycle in rules for {:?}: {}", pages, cycle),
         }
     }
 
     println!("Result2: {}", result);
 }
 
-fn sort(pages: &mut Vec<i32>, rules: &HashMap<i32, Vec<Rule>>) {
-    pages.sort_by(|a, b| {
-        for rule in rules.get(a).unwrap_or(&Vec::default()) {
-            if *b == rule.before {
-                return Ordering::Less;
-            }
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rules(pairs: &[(i32, i32)]) -> Vec<Rule> {
+        return pairs
+            .iter()
+            .map(|(page, before)| Rule {
+                page: *page,
+                before: *before,
+            })
+            .collect();
+    }
+
+    #[test]
+    fn test_order() {
+        let graph = Graph::new(&rules(&[(1, 2), (2, 3), (3, 1)]));
+        match graph.order(&[2, 3, 1]) {
+            Ok(order) => panic!("expected a cycle, got {:?}", order),
+            Err(cycle) => assert_eq!("1|2 -> 2|3 -> 3|1", cycle.to_string()),
         }
 
-        for rule in rules.get(b).unwrap_or(&Vec::default()) {
-            if *a == rule.before {
-                return Ordering::Greater;
+        let graph = Graph::new(&rules(&[(1, 2), (2, 3), (4, 3)]));
+        assert_eq!(vec![1, 4, 2, 3], graph.order(&[3, 1, 4, 2]).unwrap());
+        assert_eq!(vec![5, 1, 2], graph.order(&[5, 2, 1]).unwrap());
+    }
+
+    #[test]
+    fn test_moves() {
+        let pairs = [
+            (97, 13),
+            (97, 75),
+            (97, 47),
+            (97, 29),
+            (75, 13),
+            (75, 29),
+            (75, 47),
+            (47, 29),
+            (47, 13),
+            (29, 13),
+            (61, 13),
+            (61, 29),
+        ];
+        let graph = Graph::new(&rules(&pairs));
+        for pages in [
+            vec![97, 13, 75, 29, 47],
+            vec![61, 13, 29],
+            vec![13, 29, 47, 75, 97],
+        ] {
+            let corrected = graph.order(&pages).unwrap();
+
+            // Follow the moves as printed by the report
+            let mut result = pages.clone();
+            for m in minimal_moves(&pages, &corrected) {
+                result.retain(|x| *x != m.page);
+                let pos = match m.to {
+                    0 => 0,
+                    to => result.iter().position(|x| *x == corrected[to - 1]).unwrap() + 1,
+                };
+                result.insert(pos, m.page);
             }
-        }
 
-        return Ordering::Equal;
-    });
+            assert_eq!(corrected, result, "{:?}", pages);
+        }
+    }
 }
@@ -1,18 +1,57 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 const INPUT: &str = include_str!("input/n6.input");
 
 fn main() {
     p1();
     p2();
+
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.iter().any(|x| x == "--analyze") {
+        let obstacle = args.iter().position(|x| x == "--obstacle").map(|pos| {
+            let (x, y) = args[pos + 1].split_once(",").unwrap();
+            Position {
+                x: x.parse().unwrap(),
+                y: y.parse().unwrap(),
+            }
+        });
+        print_analysis(obstacle);
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+enum Dir {
+    Up = 0,
+    Right = 1,
+    Down = 2,
+    Left = 3,
+}
+
+impl Dir {
+    fn turn_right(self) -> Dir {
+        match self {
+            Dir::Up => Dir::Right,
+            Dir::Right => Dir::Down,
+            Dir::Down => Dir::Left,
+            Dir::Left => Dir::Up,
+        }
+    }
+
+    fn delta(self) -> (isize, isize) {
+        match self {
+            Dir::Up => (0, -1),
+            Dir::Right => (1, 0),
+            Dir::Down => (0, 1),
+            Dir::Left => (-1, 0),
+        }
+    }
 }
 
-type Direction = f64;
 #[derive(Clone)]
 enum MapElement {
     Empty,
     Wall,
-    Guard(Direction),
+    Guard(Dir),
 }
 
 type Map = Vec<Vec<MapElement>>;
@@ -22,6 +61,16 @@ struct Position {
     y: usize,
 }
 
+impl Position {
+    fn moved(self, dir: Dir, steps: usize) -> Position {
+        let (dx, dy) = dir.delta();
+        return Position {
+            x: self.x.wrapping_add_signed(dx * steps as isize),
+            y: self.y.wrapping_add_signed(dy * steps as isize),
+        };
+    }
+}
+
 fn parse() -> Map {
     let mut map = Map::new();
     for line in INPUT.lines() {
@@ -30,10 +79,10 @@ fn parse() -> Map {
                 .map(|x| match x {
                     '.' => MapElement::Empty,
                     '#' => MapElement::Wall,
-                    '^' => MapElement::Guard(std::f64::consts::PI / 2.),
-                    '>' => MapElement::Guard(0.),
-                    '<' => MapElement::Guard(std::f64::consts::PI),
-                    'v' => MapElement::Guard(std::f64::consts::PI * 3. / 2.),
+                    '^' => MapElement::Guard(Dir::Up),
+                    '>' => MapElement::Guard(Dir::Right),
+                    '<' => MapElement::Guard(Dir::Left),
+                    'v' => MapElement::Guard(Dir::Down),
                     _ => unreachable!("invalid character"),
                 })
                 .collect(),
@@ -43,131 +92,283 @@ fn parse() -> Map {
     return map;
 }
 
-fn p1() {
-    let mut map = parse();
-    let mut tracker = HashSet::new();
-    let mut guard = find_guard(&map);
+// Straight line the guard can walk from a cell before turning or leaving the map
+#[derive(Clone, Copy, Debug)]
+struct Jump {
+    steps: usize,
+    exits: bool,
+}
 
-    loop {
-        if let MapElement::Guard(dir) = map[guard.y][guard.x] {
-            // Add current position to tracker
-            tracker.insert(guard);
+// Precomputed jump for every cell and direction, so the guard moves wall to wall
+struct Jumps {
+    width: usize,
+    height: usize,
+    table: Vec<[Jump; 4]>,
+}
 
-            let next = next_position(&map, guard, dir);
-            if next.unwrap().is_none() {
-                break;
+impl Jumps {
+    fn new(map: &Map) -> Jumps {
+        let (width, height) = (map[0].len(), map.len());
+        let mut jumps = Jumps {
+            width: width,
+            height: height,
+            table: vec![
+                [Jump {
+                    steps: 0,
+                    exits: true,
+                }; 4];
+                width * height
+            ],
+        };
+
+        // Visit the cells so the one ahead in the direction is always computed first
+        for dir in [Dir::Up, Dir::Right, Dir::Down, Dir::Left] {
+            let (dx, dy) = dir.delta();
+            let ys = (0..height).collect::<Vec<_>>();
+            let xs = (0..width).collect::<Vec<_>>();
+            let ys = if dy > 0 {
+                ys.into_iter().rev().collect()
+            } else {
+                ys
+            };
+            let xs = if dx > 0 {
+                xs.into_iter().rev().collect()
+            } else {
+                xs
+            };
+
+            for y in ys.iter() {
+                for x in xs.iter() {
+                    let pos = Position { x: *x, y: *y };
+                    let next = pos.moved(dir, 1);
+                    let jump = if !jumps.contains(next) {
+                        Jump {
+                            steps: 0,
+                            exits: true,
+                        }
+                    } else if let MapElement::Wall = map[next.y][next.x] {
+                        Jump {
+                            steps: 0,
+                            exits: false,
+                        }
+                    } else {
+                        let ahead = jumps.table[jumps.index(next)][dir as usize];
+                        Jump {
+                            steps: ahead.steps + 1,
+                            exits: ahead.exits,
+                        }
+                    };
+
+                    let index = jumps.index(pos);
+                    jumps.table[index][dir as usize] = jump;
+                }
             }
+        }
+
+        return jumps;
+    }
+
+    fn contains(&self, pos: Position) -> bool {
+        return pos.x < self.width && pos.y < self.height;
+    }
+
+    fn index(&self, pos: Position) -> usize {
+        return pos.y * self.width + pos.x;
+    }
+
+    // Jump from the position, stopping before the extra obstacle if it is on the way
+    fn get(&self, pos: Position, dir: Dir, obstacle: Option<Position>) -> Jump {
+        let jump = self.table[self.index(pos)][dir as usize];
+        if let Some(obstacle) = obstacle {
+            let distance = match dir {
+                Dir::Up if obstacle.x == pos.x && obstacle.y < pos.y => pos.y - obstacle.y,
+                Dir::Down if obstacle.x == pos.x && obstacle.y > pos.y => obstacle.y - pos.y,
+                Dir::Left if obstacle.y == pos.y && obstacle.x < pos.x => pos.x - obstacle.x,
+                Dir::Right if obstacle.y == pos.y && obstacle.x > pos.x => obstacle.x - pos.x,
+                _ => usize::MAX,
+            };
 
-            // Move guard
-            let (pos, dir) = next.unwrap().unwrap();
-            map[guard.y][guard.x] = MapElement::Empty;
-            map[pos.y][pos.x] = MapElement::Guard(dir);
-            guard = pos;
+            if distance <= jump.steps {
+                return Jump {
+                    steps: distance - 1,
+                    exits: false,
+                };
+            }
         }
+
+        return jump;
     }
+}
+
+fn p1() {
+    let map = parse();
+    let jumps = Jumps::new(&map);
+    let (guard, dir) = find_guard(&map);
+
+    let tracker = route(&jumps, guard, dir)
+        .into_iter()
+        .map(|(pos, _)| pos)
+        .collect::<HashSet<_>>();
 
     println!("Result1: {}", tracker.len())
 }
 
 fn p2() {
     let map = parse();
-    let mut tracker = 0;
-    let guard = find_guard(&map);
+    let jumps = Jumps::new(&map);
+    let (guard, dir) = find_guard(&map);
 
-    // Brute force each option to cover and cause a loop
-    for y in 0..map.len() {
-        for x in 0..map[y].len() {
-            if let MapElement::Empty = map[x][y] {
-                let mut map = map.clone();
-                map[x][y] = MapElement::Wall;
-                if !can_exit_map(&mut map, &guard) {
-                    tracker += 1;
-                }
-            }
-        }
+    let obstacles = loop_obstacles(&jumps, guard, dir);
+    println!("Result2: {}", obstacles.len())
+}
+
+// Every cell the guard walks through until leaving the map, with the direction it leaves the cell
+fn route(jumps: &Jumps, guard: Position, dir: Dir) -> Vec<(Position, Dir)> {
+    match analyze(jumps, guard, dir, None) {
+        Analysis::Exits(route) => route.path,
+        Analysis::Loops(_) => panic!("the guard never leaves the map"),
     }
+}
 
-    println!("Result2: {}", tracker)
+struct Route {
+    path: Vec<(Position, Dir)>,
+    turns: Vec<Position>,
+    visits: HashMap<Position, usize>,
+    exit: Position,
 }
 
-fn can_exit_map(map: &mut Map, guard: &Position) -> bool {
-    let mut visited = HashSet::<(Position, Position)>::new();
-    let mut guard = guard.clone();
+enum Analysis {
+    Exits(Route),
+    // States repeated forever, starting from the first one reached again
+    Loops(Vec<(Position, Dir)>),
+}
+
+fn analyze(jumps: &Jumps, guard: Position, dir: Dir, obstacle: Option<Position>) -> Analysis {
+    let mut path = Vec::new();
+    let mut turns = Vec::new();
+    let mut states = HashMap::new();
+    let (mut guard, mut dir) = (guard, dir);
     loop {
-        if let MapElement::Guard(dir) = map[guard.y][guard.x] {
-            let next = next_position(&map, guard, dir);
-            if next.unwrap().is_none() {
-                // Finished
-                return true;
+        let jump = jumps.get(guard, dir, obstacle);
+        for i in 0..jump.steps + 1 {
+            // Only the last direction is kept for a cell the guard turns at
+            if i == jump.steps && !jump.exits {
+                break;
             }
 
-            // Prevent loops
-            if visited.contains(&(guard, next.unwrap().unwrap().0)) {
-                return false;
+            let state = (guard.moved(dir, i), dir);
+            if let Some(start) = states.insert(state, path.len()) {
+                return Analysis::Loops(path[start..].to_vec());
             }
-            visited.insert((guard, next.unwrap().unwrap().0));
+            path.push(state);
+        }
+
+        guard = guard.moved(dir, jump.steps);
+        if jump.exits {
+            break;
+        }
 
-            // Move guard
-            let (pos, dir) = next.unwrap().unwrap();
-            map[guard.y][guard.x] = MapElement::Empty;
-            map[pos.y][pos.x] = MapElement::Guard(dir);
-            guard = pos;
+        if turns.last() != Some(&guard) {
+            turns.push(guard);
         }
+        dir = dir.turn_right();
     }
+
+    let mut visits = HashMap::new();
+    for (pos, _) in path.iter() {
+        *visits.entry(*pos).or_insert(0) += 1;
+    }
+
+    return Analysis::Exits(Route {
+        path: path,
+        turns: turns,
+        visits: visits,
+        exit: guard,
+    });
 }
 
-fn next_position(
-    map: &Map,
-    guard: Position,
-    dir: Direction,
-) -> Option<Option<(Position, Direction)>> {
-    for i in [
-        0.,
-        std::f64::consts::PI / 2.,
-        std::f64::consts::PI,
-        std::f64::consts::PI * 3. / 2.,
-        std::f64::consts::PI * 2.,
-    ] {
-        let dir = dir - i;
-        let (x, y) = (
-            ((guard.x as f64) + 1. * dir.cos()).round(),
-            ((guard.y as f64) - 1. * dir.sin()).round(),
-        );
+fn print_analysis(obstacle: Option<Position>) {
+    let map = parse();
+    let jumps = Jumps::new(&map);
+    let (guard, dir) = find_guard(&map);
+
+    match analyze(&jumps, guard, dir, obstacle) {
+        Analysis::Exits(route) => {
+            println!("Exits at {:?} after {} steps", route.exit, route.path.len());
+            for turn in route.turns {
+                println!("  turns at {:?}", turn);
+            }
+
+            let mut repeated = route
+                .visits
+                .iter()
+                .filter(|(_, count)| **count > 1)
+                .collect::<Vec<_>>();
+            repeated.sort_by_key(|(pos, _)| (pos.y, pos.x));
+            for (pos, count) in repeated {
+                println!("  visits {:?} {} times", pos, count);
+            }
+        }
+        Analysis::Loops(cycle) => {
+            println!("Loops over {} states", cycle.len());
+            for (pos, dir) in cycle {
+                println!("  {:?} {:?}", pos, dir);
+            }
+        }
+    }
+}
 
-        // Check if it's outside
-        if x < 0. || y < 0. || y as usize >= map.len() || x as usize >= map[y as usize].len() {
-            return Some(None);
+// Positions where a new obstacle makes the guard loop. Only cells on the original route
+// can change it, and the guard walks the same way until it first reaches the obstacle,
+// so each check starts from the step right before it.
+fn loop_obstacles(jumps: &Jumps, guard: Position, dir: Dir) -> Vec<Position> {
+    let mut seen = HashSet::from([guard]);
+    let mut result = Vec::new();
+    for (pos, dir) in route(jumps, guard, dir) {
+        let next = pos.moved(dir, 1);
+        if !jumps.contains(next) || !seen.insert(next) {
+            continue;
         }
 
-        // Check if wall
-        let (x, y) = (x as usize, y as usize);
-        if let MapElement::Empty = map[y][x] {
-            return Some(Some((Position { x: x, y: y }, dir)));
+        if !can_exit_map(jumps, pos, dir, Some(next)) {
+            result.push(next);
         }
     }
-    return None;
+
+    return result;
 }
 
-fn find_guard(map: &Map) -> Position {
-    let current = map
-        .iter()
-        .map(|x| {
-            x.iter().position(|y| {
-                if let MapElement::Guard(_) = y {
-                    true
-                } else {
-                    false
-                }
-            })
-        })
-        .enumerate()
-        .filter(|(_, found)| found.is_some())
-        .map(|(y, x)| (y, x.unwrap()))
-        .collect::<Vec<_>>();
-    let current = current.first().unwrap();
-
-    return Position {
-        x: current.1,
-        y: current.0,
-    };
+fn can_exit_map(jumps: &Jumps, guard: Position, dir: Dir, obstacle: Option<Position>) -> bool {
+    // Bit per direction the guard already turned at in each cell
+    let mut visited = vec![0u8; jumps.width * jumps.height];
+    let (mut guard, mut dir) = (guard, dir);
+    loop {
+        let jump = jumps.get(guard, dir, obstacle);
+        if jump.exits {
+            return true;
+        }
+
+        guard = guard.moved(dir, jump.steps);
+
+        // Prevent loops
+        let index = jumps.index(guard);
+        if visited[index] & (1 << dir as u8) != 0 {
+            return false;
+        }
+        visited[index] |= 1 << dir as u8;
+
+        dir = dir.turn_right();
+    }
+}
+
+fn find_guard(map: &Map) -> (Position, Dir) {
+    for y in 0..map.len() {
+        for x in 0..map[y].len() {
+            if let MapElement::Guard(dir) = map[y][x] {
+                return (Position { x: x, y: y }, dir);
+            }
+        }
+    }
+
+    unreachable!("no guard in the map");
 }
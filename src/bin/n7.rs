@@ -28,13 +28,114 @@ fn parse() -> Vec<Calibration> {
 fn main() {
     p1();
     p2();
+
+    let args = std::env::args().collect::<Vec<_>>();
+    let operators = args.iter().position(|x| x == "--operators").map(|pos| {
+        args[pos + 1]
+            .split(",")
+            .map(|x| Operator::parse(x).expect("unknown operator"))
+            .collect::<Vec<_>>()
+    });
+    if let Some(operators) = &operators {
+        p3(operators, args.iter().any(|x| x == "--forward"));
+    }
+
+    let operators = operators.unwrap_or(vec![Operator::Add, Operator::Mul, Operator::Concat]);
+    if args.iter().any(|x| x == "--count") {
+        print_solutions(&operators, None, true);
+    } else if args.iter().any(|x| x == "--all") {
+        print_solutions(&operators, None, false);
+    } else if args.iter().any(|x| x == "--show") {
+        print_solutions(&operators, Some(1), false);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Xor,
+    Concat,
+}
+
+impl Operator {
+    fn parse(symbol: &str) -> Option<Operator> {
+        return match symbol {
+            "+" => Some(Operator::Add),
+            "-" => Some(Operator::Sub),
+            "*" => Some(Operator::Mul),
+            "/" => Some(Operator::Div),
+            "^" => Some(Operator::Xor),
+            "||" => Some(Operator::Concat),
+            _ => None,
+        };
+    }
+
+    fn symbol(&self) -> &'static str {
+        return match self {
+            Operator::Add => "+",
+            Operator::Sub => "-",
+            Operator::Mul => "*",
+            Operator::Div => "/",
+            Operator::Xor => "^",
+            Operator::Concat => "||",
+        };
+    }
+
+    // None when the operation is not defined for the operands or overflows
+    fn apply(&self, a: i64, b: i64) -> Option<i64> {
+        return match self {
+            Operator::Add => a.checked_add(b),
+            Operator::Sub => a.checked_sub(b),
+            Operator::Mul => a.checked_mul(b),
+            Operator::Div => a.checked_div(b),
+            Operator::Xor => Some(a ^ b),
+            Operator::Concat => concat(a, b),
+        };
+    }
+
+    // Value `a` such that `a op b == result`
+    fn inverse(&self, result: i64, b: i64) -> Inverse {
+        return match self {
+            Operator::Add => inverse_value(result.checked_sub(b)),
+            Operator::Sub => inverse_value(result.checked_add(b)),
+            Operator::Mul if b == 0 && result == 0 => Inverse::Unknown,
+            Operator::Mul if b == 0 || result.checked_rem(b) != Some(0) => Inverse::Impossible,
+            Operator::Mul => inverse_value(result.checked_div(b)),
+            Operator::Div if b == 0 => Inverse::Impossible,
+            Operator::Div => Inverse::Unknown,
+            Operator::Xor => Inverse::Value(result ^ b),
+            Operator::Concat if result < 0 || b < 0 => Inverse::Unknown,
+            Operator::Concat => match 10i64.checked_pow(digits(b)) {
+                Some(multiplier) if result % multiplier == b => Inverse::Value(result / multiplier),
+                _ => Inverse::Impossible,
+            },
+        };
+    }
+}
+
+// Values outside of i64 can never be reached by the operators
+fn inverse_value(value: Option<i64>) -> Inverse {
+    return match value {
+        Some(value) => Inverse::Value(value),
+        None => Inverse::Impossible,
+    };
+}
+
+enum Inverse {
+    Impossible,
+    Value(i64),
+    // More than one value is possible, so it has to be searched forward
+    Unknown,
 }
 
 fn p1() {
     let data = parse();
     let mut result = 0;
     for line in data {
-        if check_permutations(line.result, line.inputs[0], &line.inputs[1..], false) {
+        if check_reverse(line.result, &line.inputs, &[Operator::Add, Operator::Mul]) {
             result += line.result;
         }
     }
@@ -45,35 +146,198 @@ fn p2() {
     let data = parse();
     let mut result = 0;
     for line in data {
-        if check_permutations(line.result, line.inputs[0], &line.inputs[1..], true) {
+        if check_reverse(
+            line.result,
+            &line.inputs,
+            &[Operator::Add, Operator::Mul, Operator::Concat],
+        ) {
             result += line.result;
         }
     }
     println!("Result2: {}", result)
 }
 
-fn check_permutations(result: i64, cumulative: i64, inputs: &[i64], allow_concat: bool) -> bool {
-    if result == cumulative && inputs.is_empty() {
+fn p3(operators: &[Operator], forward: bool) {
+    let data = parse();
+    let mut result = 0;
+    for line in data {
+        let valid = match forward {
+            true => check_permutations(line.result, line.inputs[0], &line.inputs[1..], operators),
+            false => check_reverse(line.result, &line.inputs, operators),
+        };
+        if valid {
+            result += line.result;
+        }
+    }
+    println!("Result with {:?}: {}", operators, result)
+}
+
+fn print_solutions(operators: &[Operator], limit: Option<usize>, count: bool) {
+    let data = parse();
+    for line in data {
+        if count {
+            println!(
+                "{}: {} solutions",
+                line.result,
+                count_solutions(line.result, &line.inputs, operators)
+            );
+            continue;
+        }
+
+        for solution in solve(line.result, &line.inputs, operators, limit) {
+            println!("{} = {}", line.result, render(&line.inputs, &solution));
+        }
+    }
+}
+
+// Operators are always evaluated left to right
+fn check_permutations(
+    result: i64,
+    cumulative: i64,
+    inputs: &[i64],
+    operators: &[Operator],
+) -> bool {
+    let mut found = false;
+    each_forward(
+        cumulative,
+        inputs,
+        operators,
+        &|x| x == result,
+        &mut Vec::new(),
+        &mut |_| {
+            found = true;
+            false
+        },
+    );
+
+    return found;
+}
+
+// Same as `check_permutations`, but un-applying the operators from the last input
+// back to the first, which discards most of the branches early
+fn check_reverse(result: i64, inputs: &[i64], operators: &[Operator]) -> bool {
+    return !solve(result, inputs, operators, Some(1)).is_empty();
+}
+
+// Operator sequences giving the result, up to the limit
+fn solve(
+    result: i64,
+    inputs: &[i64],
+    operators: &[Operator],
+    limit: Option<usize>,
+) -> Vec<Vec<Operator>> {
+    let mut found = Vec::new();
+    each_reverse(result, inputs, operators, &mut Vec::new(), &mut |x| {
+        found.push(x.to_vec());
+        return limit.is_none_or(|limit| found.len() < limit);
+    });
+
+    return found;
+}
+
+fn count_solutions(result: i64, inputs: &[i64], operators: &[Operator]) -> usize {
+    let mut count = 0;
+    each_reverse(result, inputs, operators, &mut Vec::new(), &mut |_| {
+        count += 1;
         return true;
+    });
+
+    return count;
+}
+
+fn render(inputs: &[i64], operators: &[Operator]) -> String {
+    let mut result = inputs[0].to_string();
+    for (op, input) in operators.iter().zip(inputs[1..].iter()) {
+        result += &format!(" {} {}", op.symbol(), input);
     }
 
+    return result;
+}
+
+// Call `visit` with every operator sequence whose value is accepted, stopping when it returns false.
+// Returns false if it was stopped.
+fn each_forward(
+    cumulative: i64,
+    inputs: &[i64],
+    operators: &[Operator],
+    accept: &dyn Fn(i64) -> bool,
+    prefix: &mut Vec<Operator>,
+    visit: &mut dyn FnMut(&[Operator]) -> bool,
+) -> bool {
     if inputs.is_empty() {
-        return false;
+        return !accept(cumulative) || visit(prefix);
     }
 
-    return check_permutations(result, cumulative + inputs[0], &inputs[1..], allow_concat)
-        || check_permutations(result, cumulative * inputs[0], &inputs[1..], allow_concat)
-        || (allow_concat
-            && check_permutations(
-                result,
-                concat(cumulative, inputs[0]),
-                &inputs[1..],
-                allow_concat,
-            ));
+    for op in operators {
+        if let Some(cumulative) = op.apply(cumulative, inputs[0]) {
+            prefix.push(*op);
+            let next = each_forward(cumulative, &inputs[1..], operators, accept, prefix, visit);
+            prefix.pop();
+            if !next {
+                return false;
+            }
+        }
+    }
+
+    return true;
 }
 
-fn concat(a: i64, b: i64) -> i64 {
-    a * 10i64.pow(1 + (b as f64).log10().floor() as u32) + b
+// Same as `each_forward` starting from the result, `suffix` holds the operators
+// already un-applied, last one first
+fn each_reverse(
+    result: i64,
+    inputs: &[i64],
+    operators: &[Operator],
+    suffix: &mut Vec<Operator>,
+    visit: &mut dyn FnMut(&[Operator]) -> bool,
+) -> bool {
+    let (last, rest) = inputs.split_last().unwrap();
+    if rest.is_empty() {
+        if *last != result {
+            return true;
+        }
+        let ops = suffix.iter().rev().copied().collect::<Vec<_>>();
+        return visit(&ops);
+    }
+
+    for op in operators {
+        let next = match op.inverse(result, *last) {
+            Inverse::Impossible => true,
+            Inverse::Value(result) => {
+                suffix.push(*op);
+                let next = each_reverse(result, rest, operators, suffix, visit);
+                suffix.pop();
+                next
+            }
+            Inverse::Unknown => each_forward(
+                rest[0],
+                &rest[1..],
+                operators,
+                &|x| op.apply(x, *last) == Some(result),
+                &mut Vec::new(),
+                &mut |prefix| {
+                    let mut ops = prefix.to_vec();
+                    ops.push(*op);
+                    ops.extend(suffix.iter().rev());
+                    return visit(&ops);
+                },
+            ),
+        };
+
+        if !next {
+            return false;
+        }
+    }
+
+    return true;
+}
+
+fn concat(a: i64, b: i64) -> Option<i64> {
+    return a.checked_mul(10i64.checked_pow(digits(b))?)?.checked_add(b);
+}
+
+fn digits(x: i64) -> u32 {
+    return x.checked_ilog10().unwrap_or(0) + 1;
 }
 
 #[cfg(test)]
@@ -81,7 +345,60 @@ mod test {
     use super::*;
     #[test]
     fn test() {
-        assert_eq!(16442803, concat(1644280, 3));
-        assert_eq!(164428039, concat(1644280, 39));
+        assert_eq!(Some(16442803), concat(1644280, 3));
+        assert_eq!(Some(164428039), concat(1644280, 39));
+        assert_eq!(None, concat(i64::MAX / 10, 39));
+    }
+
+    #[test]
+    fn test_reverse() {
+        let calibrations = [
+            (190, vec![10, 19]),
+            (3267, vec![81, 40, 27]),
+            (83, vec![17, 5]),
+            (156, vec![15, 6]),
+            (7290, vec![6, 8, 6, 15]),
+            (161011, vec![16, 10, 13]),
+            (192, vec![17, 8, 14]),
+            (21037, vec![9, 7, 18, 13]),
+            (292, vec![11, 6, 16, 20]),
+            (0, vec![5, 0, 3]),
+            (2, vec![20, 10, 0]),
+        ];
+        let operators = [
+            vec![Operator::Add, Operator::Mul],
+            vec![Operator::Add, Operator::Mul, Operator::Concat],
+            vec![Operator::Sub, Operator::Div, Operator::Xor],
+            vec![Operator::Mul, Operator::Div, Operator::Concat],
+        ];
+
+        for ops in operators.iter() {
+            for (result, inputs) in calibrations.iter() {
+                assert_eq!(
+                    check_permutations(*result, inputs[0], &inputs[1..], ops),
+                    check_reverse(*result, inputs, ops),
+                    "{} {:?} {:?}",
+                    result,
+                    inputs,
+                    ops
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_solve() {
+        let operators = [Operator::Add, Operator::Mul];
+        let solutions = solve(3267, &[81, 40, 27], &operators, None);
+        let rendered = solutions
+            .iter()
+            .map(|x| render(&[81, 40, 27], x))
+            .collect::<Vec<_>>();
+        assert_eq!(2, rendered.len());
+        assert!(rendered.contains(&"81 + 40 * 27".to_string()));
+        assert!(rendered.contains(&"81 * 40 + 27".to_string()));
+        assert_eq!(2, count_solutions(3267, &[81, 40, 27], &operators));
+        assert_eq!(1, solve(3267, &[81, 40, 27], &operators, Some(1)).len());
+        assert!(!check_reverse(1, &[i64::MAX, 2], &operators));
     }
 }
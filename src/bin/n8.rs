@@ -3,7 +3,8 @@ use std::collections::{HashMap, HashSet};
 const INPUT: &str = include_str!("input/n8.input");
 
 type Map = Vec<Vec<Element>>;
-type Antennas = HashMap<char, Vec<(i32, i32)>>;
+type Antennas = HashMap<char, Vec<Vector<2>>>;
+type Vector<const N: usize> = [i32; N];
 
 #[derive(PartialEq, Eq)]
 enum Element {
@@ -37,7 +38,7 @@ fn antennas(map: &Map) -> Antennas {
                 result
                     .entry(f)
                     .or_insert(Vec::new())
-                    .push((x as i32, y as i32));
+                    .push([x as i32, y as i32]);
             }
         }
     }
@@ -48,101 +49,255 @@ fn antennas(map: &Map) -> Antennas {
 fn main() {
     p1();
     p2();
+
+    let args = std::env::args().collect::<Vec<_>>();
+    let harmonics =
+        args.iter()
+            .position(|x| x == "--harmonics")
+            .map(|pos| match args[pos + 1].as_str() {
+                "all" => Harmonics::All,
+                "double" => Harmonics::Double,
+                k => Harmonics::Multiples(k.parse().unwrap()),
+            });
+    let reduce = args.iter().any(|x| x == "--reduce");
+    if let Some(harmonics) = &harmonics {
+        p3(harmonics, reduce);
+    }
+
+    if args.iter().any(|x| x == "--report") {
+        print_report(&harmonics.unwrap_or(Harmonics::Double), reduce);
+    }
+}
+
+// Which of the points in line with two antennas are antinodes
+enum Harmonics {
+    // Only the points twice as far from one antenna as from the other
+    Double,
+    // Up to k steps beyond each antenna, including the antennas and the points between them
+    Multiples(i32),
+    // Every point in line inside the bounds
+    All,
 }
 
 fn p1() {
     let map = input();
-    let antennas = antennas(&map);
+    println!(
+        "Result1: {}",
+        count_antinodes(&map, &Harmonics::Double, false)
+    );
+}
 
-    let mut result = HashSet::<(i32, i32)>::new();
-    for (_, pos) in antennas {
-        for a in 0..pos.len() - 1 {
+fn p2() {
+    let map = input();
+    println!("Result2: {}", count_antinodes(&map, &Harmonics::All, false));
+}
+
+fn p3(harmonics: &Harmonics, reduce: bool) {
+    let map = input();
+    println!("Result: {}", count_antinodes(&map, harmonics, reduce));
+}
+
+fn count_antinodes(map: &Map, harmonics: &Harmonics, reduce: bool) -> usize {
+    let mut result = HashSet::<Vector<2>>::new();
+    for report in frequency_reports(map, harmonics, reduce) {
+        result.extend(report.antinodes);
+    }
+
+    return result.len();
+}
+
+struct FrequencyReport {
+    frequency: char,
+    antennas: usize,
+    pairs: usize,
+    antinodes: HashSet<Vector<2>>,
+}
+
+fn frequency_reports(map: &Map, harmonics: &Harmonics, reduce: bool) -> Vec<FrequencyReport> {
+    let mut result = Vec::new();
+    for (frequency, pos) in antennas(map) {
+        let mut found = HashSet::new();
+        for a in 0..pos.len() {
             for b in a + 1..pos.len() {
-                if a == b {
-                    continue;
-                }
-
-                let f1 = pos[a];
-                let f2 = pos[b];
-                let antinodes = calculate_antinodes(f1, f2);
-                for antinode in antinodes {
-                    if in_map(&map, antinode) {
-                        result.insert(antinode);
-                    }
-                }
+                let in_bounds = |x: &Vector<2>| in_map(map, x);
+                found.extend(antinodes(pos[a], pos[b], harmonics, reduce, &in_bounds));
             }
         }
+
+        result.push(FrequencyReport {
+            frequency: frequency,
+            antennas: pos.len(),
+            pairs: pos.len() * pos.len().saturating_sub(1) / 2,
+            antinodes: found,
+        });
     }
 
-    println!("Result1: {}", result.len());
+    result.sort_by_key(|x| x.frequency);
+    return result;
 }
 
-fn calculate_antinodes(a: (i32, i32), b: (i32, i32)) -> [(i32, i32); 2] {
-    fn antinode(x0: i32, x1: i32, flip: bool) -> i32 {
-        let d = (x0 - x1).abs();
-        let right = x0 > x1;
-        return flip
-            .then_some(x1 + d * right.then_some(-1).unwrap_or(1))
-            .unwrap_or(x0 + d * right.then_some(1).unwrap_or(-1));
+fn print_report(harmonics: &Harmonics, reduce: bool) {
+    let map = input();
+    let reports = frequency_reports(&map, harmonics, reduce);
+
+    let mut all = HashSet::new();
+    for report in reports.iter() {
+        let mut antinodes = report.antinodes.iter().collect::<Vec<_>>();
+        antinodes.sort_by_key(|[x, y]| (*y, *x));
+        println!(
+            "{}: {} antennas, {} pairs, {} antinodes {:?}",
+            report.frequency,
+            report.antennas,
+            report.pairs,
+            antinodes.len(),
+            antinodes
+        );
+        all.extend(report.antinodes.iter().copied());
+    }
+
+    for (i, a) in reports.iter().enumerate() {
+        for b in reports[i + 1..].iter() {
+            let shared = a.antinodes.intersection(&b.antinodes).count();
+            if shared > 0 {
+                println!(
+                    "{} and {} share {} antinodes",
+                    a.frequency, b.frequency, shared
+                );
+            }
+        }
     }
 
-    return [
-        (antinode(a.0, b.0, false), antinode(a.1, b.1, false)),
-        (antinode(a.0, b.0, true), antinode(a.1, b.1, true)),
-    ];
+    println!("{}", render(&map, &all));
 }
 
-fn in_map(map: &Map, (x, y): (i32, i32)) -> bool {
-    return x >= 0
-        && y >= 0
-        && y < map.len().try_into().unwrap()
-        && x < map[y as usize].len().try_into().unwrap();
+// Map with the antinodes drawn as `#`, antennas are kept so they can still be seen
+fn render(map: &Map, antinodes: &HashSet<Vector<2>>) -> String {
+    let mut result = String::new();
+    for y in 0..map.len() {
+        for x in 0..map[y].len() {
+            result.push(match map[y][x] {
+                Element::Antenna(f) => f,
+                Element::Empty if antinodes.contains(&[x as i32, y as i32]) => '#',
+                Element::Empty => '.',
+            });
+        }
+        result.push('\n');
+    }
+
+    return result;
 }
 
-fn p2() {
-    let map = input();
-    let antennas = antennas(&map);
+// Antinodes for a pair of antennas, stepping along the line between them.
+// With `reduce`, the step is divided by the gcd of its components, so every
+// grid point in line is found, including the ones between the antennas.
+fn antinodes<const N: usize>(
+    a: Vector<N>,
+    b: Vector<N>,
+    harmonics: &Harmonics,
+    reduce: bool,
+    in_bounds: &dyn Fn(&Vector<N>) -> bool,
+) -> Vec<Vector<N>> {
+    // Antennas at the same point don't define a line
+    let distance = sub(b, a);
+    if distance.iter().all(|x| *x == 0) {
+        return Vec::new();
+    }
 
-    let mut result = HashSet::<(i32, i32)>::new();
-    for (_, pos) in antennas {
-        for a in 0..pos.len() - 1 {
-            for b in a + 1..pos.len() {
-                if a == b {
-                    continue;
-                }
-
-                let f1 = pos[a];
-                let f2 = pos[b];
-                let dist = distance(f1, f2);
-
-                let (mut x, mut y) = (f1.0, f1.1);
-                loop {
-                    result.insert((x, y));
-                    x += dist.0;
-                    y += dist.1;
-                    if !in_map(&map, (x, y)) {
-                        break;
-                    }
-                }
-
-                let (mut x, mut y) = (f1.0, f1.1);
-                loop {
-                    result.insert((x, y));
-                    x -= dist.0;
-                    y -= dist.1;
-                    if !in_map(&map, (x, y)) {
-                        break;
-                    }
-                }
+    let divisor = match reduce {
+        true => distance.iter().fold(0, |acc, x| gcd(acc, x.abs())),
+        false => 1,
+    };
+
+    let step = distance.map(|x| x / divisor);
+    // b is at `a + divisor * step`
+    let steps = match harmonics {
+        Harmonics::Double => vec![-divisor, 2 * divisor],
+        Harmonics::Multiples(k) => (-k..divisor + k + 1).collect(),
+        Harmonics::All => {
+            let mut steps = Vec::new();
+            let mut t = 0;
+            while in_bounds(&add(a, scale(step, t))) {
+                steps.push(t);
+                t += 1;
             }
+            let mut t = -1;
+            while in_bounds(&add(a, scale(step, t))) {
+                steps.push(t);
+                t -= 1;
+            }
+            steps
         }
+    };
+
+    return steps
+        .into_iter()
+        .map(|t| add(a, scale(step, t)))
+        .filter(|x| in_bounds(x))
+        .collect();
+}
+
+fn add<const N: usize>(a: Vector<N>, b: Vector<N>) -> Vector<N> {
+    return std::array::from_fn(|i| a[i] + b[i]);
+}
+
+fn sub<const N: usize>(a: Vector<N>, b: Vector<N>) -> Vector<N> {
+    return std::array::from_fn(|i| a[i] - b[i]);
+}
+
+fn scale<const N: usize>(a: Vector<N>, k: i32) -> Vector<N> {
+    return a.map(|x| x * k);
+}
+
+fn gcd(a: i32, b: i32) -> i32 {
+    if b == 0 {
+        return a;
     }
+    return gcd(b, a % b);
+}
 
-    println!("Result2: {}", result.len());
+fn in_map(map: &Map, [x, y]: &Vector<2>) -> bool {
+    return *x >= 0
+        && *y >= 0
+        && *y < map.len().try_into().unwrap()
+        && *x < map[*y as usize].len().try_into().unwrap();
 }
 
-fn distance(a: (i32, i32), b: (i32, i32)) -> (i32, i32) {
-    let dx = b.0 - a.0;
-    let dy = b.1 - a.1;
-    return (dx, dy);
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_antinodes() {
+        let bounds = |x: &Vector<3>| x.iter().all(|x| (0..10).contains(x));
+        let mut result = antinodes([2, 2, 2], [4, 4, 4], &Harmonics::Double, false, &bounds);
+        result.sort();
+        assert_eq!(vec![[0, 0, 0], [6, 6, 6]], result);
+
+        let mut result = antinodes(
+            [2, 2, 2],
+            [4, 4, 4],
+            &Harmonics::Multiples(1),
+            true,
+            &bounds,
+        );
+        result.sort();
+        assert_eq!(
+            vec![[1, 1, 1], [2, 2, 2], [3, 3, 3], [4, 4, 4], [5, 5, 5]],
+            result
+        );
+
+        assert_eq!(
+            10,
+            antinodes([2, 2, 2], [4, 4, 4], &Harmonics::All, true, &bounds).len()
+        );
+        assert_eq!(
+            5,
+            antinodes([2, 2, 2], [4, 4, 4], &Harmonics::All, false, &bounds).len()
+        );
+
+        for reduce in [false, true] {
+            let result = antinodes([2, 2, 2], [2, 2, 2], &Harmonics::All, reduce, &bounds);
+            assert!(result.is_empty());
+        }
+    }
 }
@@ -1,3 +1,5 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ops::Mul;
 
 const INPUT: &str = include_str!("input/n9.input");
@@ -5,6 +7,14 @@ const INPUT: &str = include_str!("input/n9.input");
 fn main() {
     p1();
     p2();
+
+    if std::env::args().any(|x| x == "--compare") {
+        compare();
+    }
+
+    if std::env::args().any(|x| x == "--trace") {
+        trace();
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -14,10 +24,14 @@ enum Disk {
 }
 
 fn input() -> Vec<Disk> {
+    return decode(INPUT.trim());
+}
+
+fn decode(map: &str) -> Vec<Disk> {
     let mut is_file = true;
     let mut file_id = 0;
     let mut result = Vec::new();
-    for el in INPUT.trim().chars() {
+    for el in map.chars() {
         let num = el.to_digit(10).unwrap();
         for _ in 0..num {
             match is_file {
@@ -34,6 +48,145 @@ fn input() -> Vec<Disk> {
     return result;
 }
 
+// Inverse of `input`, None if the layout can not be written in the dense format:
+// files out of id order, split in several places, longer than 9 blocks, or free space
+// too long to be split by files of length 0. Where files of length 0 were is lost in
+// the layout, so they are written right before the next file with no space after them,
+// `120311` comes back as `150011`, unless they are needed to split a long free space.
+fn encode(input: &[Disk]) -> Option<String> {
+    let mut runs = Vec::new();
+    let mut i = 0;
+    while i < input.len() {
+        let len = input[i..].iter().take_while(|x| **x == input[i]).count();
+        runs.push((input[i], len));
+        i += len;
+    }
+
+    let mut result = String::new();
+    let mut next_id = 0;
+    let mut is_file = true;
+    for (i, (disk, len)) in runs.iter().enumerate() {
+        match disk {
+            Disk::File(id) => {
+                // No space between two files
+                if !is_file {
+                    result.push('0');
+                }
+                if *id < next_id {
+                    return None;
+                }
+                for _ in next_id..*id {
+                    result.push_str("00");
+                }
+                result.push(char::from_digit((*len).try_into().ok()?, 10)?);
+                next_id = id + 1;
+                is_file = false;
+            }
+            Disk::None => {
+                // Disk starting with a file of length 0
+                if is_file {
+                    result.push('0');
+                    next_id += 1;
+                }
+
+                // Files of length 0 before the next file can split the space. After the
+                // last file there can be any number of them.
+                let mut pending = match runs[i + 1..].iter().find_map(|(x, _)| match x {
+                    Disk::File(id) => Some(*id),
+                    Disk::None => None,
+                }) {
+                    Some(id) => id.saturating_sub(next_id),
+                    None => u32::MAX,
+                };
+
+                let mut left = *len;
+                loop {
+                    let part = left.min(9);
+                    result.push(char::from_digit(part as u32, 10).unwrap());
+                    left -= part;
+                    if left == 0 {
+                        break;
+                    }
+                    if pending == 0 {
+                        return None;
+                    }
+                    result.push('0');
+                    next_id += 1;
+                    pending -= 1;
+                }
+                is_file = true;
+            }
+        }
+    }
+
+    return Some(result);
+}
+
+// Layout with one character per block, files show the last digit of their id
+fn render(input: &[Disk]) -> String {
+    return input
+        .iter()
+        .map(|x| match x {
+            Disk::None => '.',
+            Disk::File(id) => char::from_digit(id % 10, 10).unwrap(),
+        })
+        .collect();
+}
+
+fn trace() {
+    let mut disk = input();
+    if let Some(map) = encode(&disk) {
+        println!("{}", map);
+    }
+    println!("{}", render(&disk));
+
+    let mut files = input_spans();
+    for m in compress_continuous(&mut files) {
+        for i in 0..m.len {
+            disk[m.to + i] = Disk::File(m.id);
+            disk[m.from + i] = Disk::None;
+        }
+        println!(
+            "move file {} ({} blocks) from {} to {}",
+            m.id, m.len, m.from, m.to
+        );
+        println!("{}", render(&disk));
+    }
+
+    println!("Checksum: {}", checksum(&disk));
+}
+
+// File stored in consecutive blocks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Span {
+    id: u32,
+    start: usize,
+    len: usize,
+}
+
+// Same as `input`, but keeping each file as a single span
+fn input_spans() -> Vec<Span> {
+    return decode_spans(INPUT.trim());
+}
+
+fn decode_spans(map: &str) -> Vec<Span> {
+    let mut result = Vec::new();
+    let mut start = 0;
+    for (i, el) in map.chars().enumerate() {
+        let len = el.to_digit(10).unwrap() as usize;
+        if i % 2 == 0 {
+            result.push(Span {
+                id: (i / 2) as u32,
+                start: start,
+                len: len,
+            });
+        }
+        start += len;
+    }
+
+    return result;
+}
+
 fn p1() {
     let mut input = input();
     compress_fragmented(&mut input);
@@ -41,9 +194,9 @@ fn p1() {
 }
 
 fn p2() {
-    let mut input = input();
-    compress_continuous(&mut input);
-    println!("Result2: {:?}", checksum(&input));
+    let mut files = input_spans();
+    compress_continuous(&mut files);
+    println!("Result2: {:?}", span_checksum(&files));
 }
 
 fn compress_fragmented(input: &mut Vec<Disk>) {
@@ -68,99 +221,326 @@ fn compress_fragmented(input: &mut Vec<Disk>) {
     }
 }
 
-fn compress_continuous(input: &mut Vec<Disk>) {
-    let mut current_block = input.len() - 1;
-    while let Some(next_block) = next_block(input, current_block) {
-        if next_block.position == 0 {
-            break;
-        }
+fn compress_continuous(files: &mut [Span]) -> Vec<Move> {
+    return compress(
+        files,
+        &Strategy {
+            fit: Fit::First,
+            order: Order::HighestId,
+        },
+    );
+}
 
-        // Next iter
-        current_block = next_block.position - 1;
+// Which free space a file is moved to, only spaces to the left of the file are considered
+#[derive(Clone, Copy, Debug)]
+enum Fit {
+    // Leftmost space
+    First,
+    // Smallest space, leftmost on ties
+    Best,
+    // Largest space, leftmost on ties
+    Worst,
+}
 
-        // select free spot
-        let free_spot = match lookup_free(input, next_block.size) {
-            None => continue,
-            Some(pos) => pos,
-        };
+// Order in which the files are moved, each one is tried once
+#[derive(Clone, Copy, Debug)]
+enum Order {
+    HighestId,
+    LowestId,
+    Largest,
+    Smallest,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Strategy {
+    fit: Fit,
+    order: Order,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Move {
+    id: u32,
+    from: usize,
+    to: usize,
+    len: usize,
+}
+
+// Move whole files to free space on their left
+fn compress(files: &mut [Span], strategy: &Strategy) -> Vec<Move> {
+    let mut free = FreeSpace::new(files);
+    for (start, len) in free_spans(files) {
+        free.add(start, len);
+    }
+
+    let mut order = (0..files.len()).collect::<Vec<_>>();
+    match strategy.order {
+        Order::HighestId => order.sort_by_key(|x| Reverse(files[*x].id)),
+        Order::LowestId => order.sort_by_key(|x| files[*x].id),
+        Order::Largest => order.sort_by_key(|x| (Reverse(files[*x].len), Reverse(files[*x].id))),
+        Order::Smallest => order.sort_by_key(|x| (files[*x].len, Reverse(files[*x].id))),
+    }
 
-        if free_spot >= next_block.position {
+    let mut moves = Vec::new();
+    for i in order {
+        let file = files[i];
+        if file.len == 0 {
             continue;
         }
 
-        // Move block
-        let file_id = match input[next_block.position] {
-            Disk::File(id) => id,
-            _ => unreachable!(),
+        let (start, len) = match free.find(file.len, file.start, strategy.fit) {
+            Some(x) => x,
+            None => continue,
         };
 
-        for i in 0..next_block.size {
-            input[free_spot + i] = Disk::File(file_id);
-            input[next_block.position + i] = Disk::None;
+        free.remove(start);
+        if len > file.len {
+            free.add(start + file.len, len - file.len);
+        }
+
+        // The space left by the file joins the free spaces next to it
+        let (mut gap_start, mut gap_len) = (file.start, file.len);
+        if let Some((prev, prev_len)) = free.before(gap_start) {
+            if prev + prev_len == gap_start {
+                free.remove(prev);
+                (gap_start, gap_len) = (prev, prev_len + gap_len);
+            }
         }
+        if let Some(next_len) = free.remove(file.start + file.len) {
+            gap_len += next_len;
+        }
+        free.add(gap_start, gap_len);
+
+        files[i].start = start;
+        moves.push(Move {
+            id: file.id,
+            from: file.start,
+            to: start,
+            len: file.len,
+        });
     }
+
+    return moves;
 }
 
-fn lookup_free(input: &Vec<Disk>, size: usize) -> Option<usize> {
-    let mut free_size: usize = 0;
-    for i in 0..input.len() {
-        match input[i] {
-            Disk::None => free_size += 1,
-            _ => free_size = 0,
+// Free spaces by position, and indexed by size. Spaces smaller than the largest file
+// are in a set of positions per size. Any file fits in the larger ones, so they are
+// all in one set, plus a tree to find the smallest or largest of them left of a position.
+struct FreeSpace {
+    spans: BTreeMap<usize, usize>,
+    by_size: Vec<BTreeSet<usize>>,
+    large: BTreeSet<usize>,
+    tree: SpanTree,
+}
+
+impl FreeSpace {
+    fn new(files: &[Span]) -> FreeSpace {
+        let largest = files.iter().map(|x| x.len).max().unwrap_or(0);
+        let end = files.iter().map(|x| x.start + x.len).max().unwrap_or(0);
+        return FreeSpace {
+            spans: BTreeMap::new(),
+            by_size: vec![BTreeSet::new(); largest],
+            large: BTreeSet::new(),
+            tree: SpanTree::new(end),
+        };
+    }
+
+    fn add(&mut self, start: usize, len: usize) {
+        self.spans.insert(start, len);
+        if len < self.by_size.len() {
+            self.by_size[len].insert(start);
+        } else {
+            self.large.insert(start);
+            self.tree.set(start, len);
         }
+    }
 
-        if free_size == size {
-            return Some(i - free_size + 1);
+    fn remove(&mut self, start: usize) -> Option<usize> {
+        let len = self.spans.remove(&start)?;
+        if len < self.by_size.len() {
+            self.by_size[len].remove(&start);
+        } else {
+            self.large.remove(&start);
+            self.tree.set(start, 0);
         }
+
+        return Some(len);
     }
 
-    return None;
+    fn before(&self, start: usize) -> Option<(usize, usize)> {
+        return self
+            .spans
+            .range(..start)
+            .next_back()
+            .map(|(start, len)| (*start, *len));
+    }
+
+    // Space of at least `len` blocks starting before `end`, as (start, length)
+    fn find(&self, len: usize, end: usize, fit: Fit) -> Option<(usize, usize)> {
+        let small = (len..self.by_size.len())
+            .filter_map(|x| self.by_size[x].first().map(|start| (*start, x)))
+            .filter(|(start, _)| *start < end);
+
+        return match fit {
+            Fit::First => small
+                .chain(self.large.first().map(|x| (*x, self.spans[x])))
+                .filter(|(start, _)| *start < end)
+                .min(),
+            Fit::Best => small
+                .min_by_key(|(start, len)| (*len, *start))
+                .or(self.tree.smallest(end)),
+            Fit::Worst => self
+                .tree
+                .largest(end)
+                .or(small.min_by_key(|(start, len)| (Reverse(*len), *start))),
+        };
+    }
 }
 
-struct NextBlock {
-    position: usize,
+// Segment tree over the disk positions with the length of the space starting at each one,
+// 0 if none. Each node keeps its smallest and largest space, leftmost on ties.
+struct SpanTree {
     size: usize,
+    // (length, start), usize::MAX length when empty
+    smallest: Vec<(usize, usize)>,
+    // (length, Reverse(start)), 0 length when empty
+    largest: Vec<(usize, Reverse<usize>)>,
 }
 
-fn next_block(input: &Vec<Disk>, from: usize) -> Option<NextBlock> {
-    let mut has_block = None;
-    let mut size = 0;
-    for i in (0..from + 1).rev() {
-        if input[i] == Disk::None && has_block.is_none() {
-            continue;
+impl SpanTree {
+    fn new(size: usize) -> SpanTree {
+        let size = size.next_power_of_two();
+        return SpanTree {
+            size: size,
+            smallest: vec![(usize::MAX, usize::MAX); 2 * size],
+            largest: vec![(0, Reverse(usize::MAX)); 2 * size],
+        };
+    }
+
+    fn set(&mut self, start: usize, len: usize) {
+        let mut i = start + self.size;
+        self.smallest[i] = if len == 0 {
+            (usize::MAX, usize::MAX)
+        } else {
+            (len, start)
+        };
+        self.largest[i] = (len, Reverse(start));
+
+        while i > 1 {
+            i /= 2;
+            self.smallest[i] = self.smallest[2 * i].min(self.smallest[2 * i + 1]);
+            self.largest[i] = self.largest[2 * i].max(self.largest[2 * i + 1]);
         }
+    }
 
-        match input[i] {
-            Disk::None => {
-                return Some(NextBlock {
-                    position: i + 1,
-                    size: size,
-                })
+    // Nodes covering the positions before `end`
+    fn prefix(&self, end: usize) -> Vec<usize> {
+        let mut result = Vec::new();
+        let (mut left, mut right) = (self.size, self.size + end.min(self.size));
+        while left < right {
+            if left % 2 == 1 {
+                result.push(left);
+                left += 1;
             }
-            Disk::File(id) => {
-                if has_block == None {
-                    size += 1;
-                    has_block = Some(id);
-                } else if has_block.unwrap() == id {
-                    size += 1;
-                } else {
-                    return Some(NextBlock {
-                        position: i + 1,
-                        size: size,
-                    });
-                }
+            if right % 2 == 1 {
+                right -= 1;
+                result.push(right);
             }
+            left /= 2;
+            right /= 2;
         }
+
+        return result;
     }
 
-    if has_block.is_some() {
-        return Some(NextBlock {
-            position: 0,
-            size: size,
-        });
+    fn smallest(&self, end: usize) -> Option<(usize, usize)> {
+        let (len, start) = self
+            .prefix(end)
+            .into_iter()
+            .map(|x| self.smallest[x])
+            .min()?;
+        return (len != usize::MAX).then_some((start, len));
     }
 
-    return None;
+    fn largest(&self, end: usize) -> Option<(usize, usize)> {
+        let (len, Reverse(start)) = self
+            .prefix(end)
+            .into_iter()
+            .map(|x| self.largest[x])
+            .max()?;
+        return (len != 0).then_some((start, len));
+    }
+}
+
+struct Stats {
+    checksum: usize,
+    // Free spaces left between files
+    fragmentation: usize,
+    moved_blocks: usize,
+    moved_files: usize,
+}
+
+fn stats(files: &[Span], moves: &[Move]) -> Stats {
+    return Stats {
+        checksum: span_checksum(files),
+        fragmentation: free_spans(files).len(),
+        moved_blocks: moves.iter().map(|x| x.len).sum(),
+        moved_files: moves.len(),
+    };
+}
+
+fn compare() {
+    let files = input_spans();
+    for fit in [Fit::First, Fit::Best, Fit::Worst] {
+        for order in [
+            Order::HighestId,
+            Order::LowestId,
+            Order::Largest,
+            Order::Smallest,
+        ] {
+            let mut files = files.clone();
+            let moves = compress(
+                &mut files,
+                &Strategy {
+                    fit: fit,
+                    order: order,
+                },
+            );
+            let stats = stats(&files, &moves);
+            println!(
+                "{:<6} {:<10} checksum {:>16}, {:>6} gaps, {:>7} blocks and {:>6} files moved",
+                format!("{:?}", fit),
+                format!("{:?}", order),
+                stats.checksum,
+                stats.fragmentation,
+                stats.moved_blocks,
+                stats.moved_files
+            );
+        }
+    }
+}
+
+// Gaps between the files as (start, length), ordered by position
+fn free_spans(files: &[Span]) -> Vec<(usize, usize)> {
+    let mut sorted = files.iter().filter(|x| x.len > 0).collect::<Vec<_>>();
+    sorted.sort_by_key(|x| x.start);
+
+    let mut result = Vec::new();
+    let mut end = 0;
+    for file in sorted {
+        if file.start > end {
+            result.push((end, file.start - end));
+        }
+        end = end.max(file.start + file.len);
+    }
+
+    return result;
+}
+
+fn span_checksum(files: &[Span]) -> usize {
+    return files
+        .iter()
+        .map(|x| x.id as usize * (x.len * x.start + x.len * x.len.saturating_sub(1) / 2))
+        .sum();
 }
 
 fn checksum(input: &Vec<Disk>) -> usize {
@@ -199,3 +579,101 @@ fn next_used(input: &Vec<Disk>, current: usize) -> Option<usize> {
 
     return None;
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        for map in ["2333133121414131402", "12345", "0213", "90909"] {
+            assert_eq!(Some(map.to_string()), encode(&decode(map)));
+        }
+        assert_eq!(Some("150011".to_string()), encode(&decode("120311")));
+        assert_eq!(Some("15".to_string()), encode(&decode("1203")));
+        assert_eq!(Some("100011".to_string()), encode(&decode("100011")));
+        for map in ["1909", "0909", "190911", "09090"] {
+            let encoded = encode(&decode(map)).unwrap();
+            assert_eq!(decode(map), decode(&encoded), "{} as {}", map, encoded);
+        }
+        assert_eq!(Some("1909".to_string()), encode(&decode("1909")));
+        assert_eq!(Some("19091".to_string()), encode(&decode("19091")));
+
+        // No file of length 0 left to split the space between files 0 and 1
+        let mut disk = vec![Disk::File(0)];
+        disk.extend([Disk::None; 10]);
+        disk.push(Disk::File(1));
+        assert_eq!(None, encode(&disk));
+
+        let mut disk = decode("12345");
+        assert_eq!("0..111....22222", render(&disk));
+        compress_fragmented(&mut disk);
+        assert_eq!("022111222......", render(&disk));
+        assert_eq!(None, encode(&disk));
+    }
+
+    // Block by block compaction: each file, from the highest id, goes to the leftmost
+    // run of free blocks on its left that is long enough
+    fn compress_blocks(disk: &mut [Disk]) {
+        let files = disk.iter().filter_map(|x| match x {
+            Disk::File(id) => Some(*id),
+            Disk::None => None,
+        });
+        for id in (0..files.max().map_or(0, |x| x + 1)).rev() {
+            let from = disk.iter().position(|x| *x == Disk::File(id)).unwrap();
+            let len = disk[from..]
+                .iter()
+                .take_while(|x| **x == Disk::File(id))
+                .count();
+
+            let mut run = 0;
+            for pos in 0..from {
+                run = if disk[pos] == Disk::None { run + 1 } else { 0 };
+                if run == len {
+                    for i in 0..len {
+                        disk[pos + 1 - len + i] = Disk::File(id);
+                        disk[from + i] = Disk::None;
+                    }
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_compress() {
+        let mut maps = vec!["2333133121414131402".to_string(), "12345".to_string()];
+        let mut seed = 12345u64;
+        for len in [1, 2, 7, 40, 200, 1001] {
+            let mut map = String::new();
+            for i in 0..len {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                // Files have at least one block, so each id can be found on the disk
+                let digit = (seed >> 33) % 10;
+                map.push(
+                    char::from_digit(if i % 2 == 0 { digit.max(1) } else { digit } as u32, 10)
+                        .unwrap(),
+                );
+            }
+            maps.push(map);
+        }
+
+        for map in maps {
+            let mut disk = decode(&map);
+            compress_blocks(&mut disk);
+
+            let mut files = decode_spans(&map);
+            compress_continuous(&mut files);
+            let mut result = vec![Disk::None; disk.len()];
+            for file in files {
+                for i in 0..file.len {
+                    result[file.start + i] = Disk::File(file.id);
+                }
+            }
+
+            assert_eq!(disk, result, "{}", map);
+        }
+    }
+}